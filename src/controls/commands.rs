@@ -1,6 +1,11 @@
-use poise::command;
+use poise::{command, serenity_prelude::Role};
 
-use crate::{media::plugin::get_media_player, utils::responses::Responses, CommandResult, Context};
+use crate::{
+    database::plugin::get_db_plugin, media::plugin::get_media_player, utils::responses::Responses,
+    CommandResult, Context,
+};
+
+use super::permissions::dj_only;
 
 /// Join your VC
 #[command(slash_command, prefix_command, category = "controls")]
@@ -11,7 +16,12 @@ pub async fn join(ctx: Context<'_>) -> CommandResult {
 }
 
 /// Leave the VC
-#[command(slash_command, prefix_command, category = "controls")]
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_only",
+    category = "controls"
+)]
 pub async fn leave(ctx: Context<'_>) -> CommandResult {
     let media_player = get_media_player(ctx.discord()).await.unwrap();
 
@@ -24,14 +34,8 @@ pub async fn leave(ctx: Context<'_>) -> CommandResult {
     let has_handler = manager.get(guild_id).is_some();
 
     if has_handler {
-        let res = media_player.quit(guild_id).await;
-        match res {
-            Ok(_) => (),
-            Err(err) => ctx.warn(err).await,
-        }
-
-        if let Err(err) = manager.remove(guild_id).await {
-            ctx.error(format!("Failed: {:?}", err)).await;
+        if let Err(err) = super::leave_channel(&manager, &media_player, guild_id).await {
+            ctx.warn(err).await;
         }
 
         ctx.info("Left voice channel").await;
@@ -43,7 +47,12 @@ pub async fn leave(ctx: Context<'_>) -> CommandResult {
 }
 
 /// Mute the bot
-#[command(slash_command, prefix_command, category = "controls")]
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_only",
+    category = "controls"
+)]
 pub async fn mute(ctx: Context<'_>) -> CommandResult {
     let guild_id = ctx.guild_id().unwrap();
 
@@ -77,7 +86,12 @@ pub async fn mute(ctx: Context<'_>) -> CommandResult {
 }
 
 /// Deafen the bot
-#[command(slash_command, prefix_command, category = "controls")]
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_only",
+    category = "controls"
+)]
 pub async fn deafen(ctx: Context<'_>) -> CommandResult {
     let guild_id = ctx.guild_id().unwrap();
 
@@ -111,7 +125,12 @@ pub async fn deafen(ctx: Context<'_>) -> CommandResult {
 }
 
 /// Unmute the bot
-#[command(slash_command, prefix_command, category = "controls")]
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_only",
+    category = "controls"
+)]
 pub async fn unmute(ctx: Context<'_>) -> CommandResult {
     let guild_id = ctx.guild_id().unwrap();
 
@@ -135,7 +154,12 @@ pub async fn unmute(ctx: Context<'_>) -> CommandResult {
 }
 
 /// Undeafen the bot
-#[command(slash_command, prefix_command, category = "controls")]
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_only",
+    category = "controls"
+)]
 pub async fn undeafen(ctx: Context<'_>) -> CommandResult {
     let guild_id = ctx.guild_id().unwrap();
 
@@ -157,3 +181,35 @@ pub async fn undeafen(ctx: Context<'_>) -> CommandResult {
 
     Ok(())
 }
+
+/// Set the role allowed to control playback, or leave it empty to let everyone
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "dj-role",
+    category = "controls"
+)]
+pub async fn dj_role(
+    ctx: Context<'_>,
+    #[description = "DJ role"] role: Option<Role>,
+) -> CommandResult {
+    let guild_id = ctx.guild_id().unwrap();
+    let db = get_db_plugin(ctx.discord()).await.unwrap();
+
+    if let Err(err) = db.set_dj_role(guild_id, role.as_ref().map(|role| role.id)) {
+        ctx.error(err).await;
+        return Ok(());
+    }
+
+    match role {
+        Some(role) => {
+            ctx.info(format!("Only {} can control playback now", role.name))
+                .await
+        }
+        None => ctx.info("Everyone can control playback now").await,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{Context as SerenityContext, GuildId, VoiceState};
+use tracing::info;
+
+use crate::{
+    config,
+    media::{global_media_player::GlobalMediaPlayer, plugin::get_media_player},
+};
+
+use super::{leave_channel, listener_count};
+
+/// Leaves the voice channel once the queue has been empty for the idle timeout
+pub async fn watch_idle(
+    ctx: SerenityContext,
+    media_player: Arc<GlobalMediaPlayer>,
+    guild_id: GuildId,
+) {
+    let timeout = match config::timeouts::idle(guild_id) {
+        Some(timeout) => timeout,
+        None => return,
+    };
+
+    loop {
+        let idle_since = match media_player.idle_since(guild_id).await {
+            Ok(idle_since) => idle_since,
+            // The player is gone, someone else already left
+            Err(_) => return,
+        };
+
+        let idle_for = idle_since.map(|instant| instant.elapsed());
+
+        match idle_for {
+            Some(idle_for) if idle_for >= timeout => {
+                info!("Leaving {} after being idle", guild_id);
+                // Leaving aborts this watcher, so it is done in a task of its own
+                tokio::spawn(async move {
+                    disconnect(
+                        &ctx,
+                        &media_player,
+                        guild_id,
+                        "Left voice channel due to inactivity",
+                    )
+                    .await;
+                });
+                return;
+            }
+            Some(idle_for) => tokio::time::sleep(timeout - idle_for).await,
+            None => tokio::time::sleep(timeout).await,
+        }
+    }
+}
+
+/// Schedules leaving the voice channel if the bot was left alone in it. Each update replaces
+/// the guild's timer, so the timeout counts from the latest departure and rejoining cancels it.
+pub async fn handle_voice_state_update(ctx: &SerenityContext, new: &VoiceState) {
+    let guild_id = match new.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    let timeout = match config::timeouts::alone(guild_id) {
+        Some(timeout) => timeout,
+        None => return,
+    };
+
+    let media_player = match get_media_player(ctx).await {
+        Some(media_player) => media_player,
+        None => return,
+    };
+
+    let alone_timer = is_alone(ctx, guild_id).then(|| {
+        let ctx = ctx.clone();
+        let media_player = media_player.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+
+            if !is_alone(&ctx, guild_id) {
+                return;
+            }
+
+            info!("Leaving {} after being alone", guild_id);
+            // Leaving aborts this timer, so it is done in a task of its own
+            tokio::spawn(async move {
+                disconnect(
+                    &ctx,
+                    &media_player,
+                    guild_id,
+                    "Left voice channel since everyone else left",
+                )
+                .await;
+            });
+        })
+    });
+
+    // The bot isn't in a channel of the guild, so there is nothing to leave
+    let _ = media_player.set_alone_timer(guild_id, alone_timer).await;
+}
+
+/// Returns true if the bot is in a voice channel with no other users
+fn is_alone(ctx: &SerenityContext, guild_id: GuildId) -> bool {
+    listener_count(ctx, guild_id) == Some(0)
+}
+
+async fn disconnect(
+    ctx: &SerenityContext,
+    media_player: &GlobalMediaPlayer,
+    guild_id: GuildId,
+    notice: &str,
+) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    // Grab the channel before quitting since the player is dropped afterwards
+    let message_ctx = media_player.message_ctx(guild_id).await;
+
+    if leave_channel(&manager, media_player, guild_id)
+        .await
+        .is_ok()
+    {
+        if let Ok(message_ctx) = message_ctx {
+            message_ctx.send_info(notice).await;
+        }
+    }
+}
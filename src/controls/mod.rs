@@ -1,11 +1,23 @@
 pub mod commands;
+pub mod inactivity;
+pub mod permissions;
+
+use std::sync::Arc;
+
+use poise::serenity_prelude::{ChannelId, Context as SerenityContext, GuildId, UserId};
+use songbird::Songbird;
+use tracing::warn;
 
 use crate::{
-    media::global_media_player::GlobalMediaPlayer, utils::responses::Responses, CommandResult,
-    Context,
+    media::{global_media_player::GlobalMediaPlayer, plugin::get_metadata_provider},
+    utils::{message_context::MessageContext, responses::Responses},
+    CommandResult, Context,
 };
 
-pub async fn join_channel(media_player: &GlobalMediaPlayer, ctx: Context<'_>) -> CommandResult {
+pub async fn join_channel(
+    media_player: &Arc<GlobalMediaPlayer>,
+    ctx: Context<'_>,
+) -> CommandResult {
     let guild = ctx.guild().unwrap();
     let guild_id = guild.id;
 
@@ -23,22 +35,128 @@ pub async fn join_channel(media_player: &GlobalMediaPlayer, ctx: Context<'_>) ->
         }
     };
 
-    let manager = songbird::get(ctx.discord())
+    let message_ctx = MessageContext::from(ctx);
+
+    if let Err(err) = connect(
+        ctx.discord(),
+        media_player,
+        guild_id,
+        connect_to,
+        message_ctx,
+    )
+    .await
+    {
+        ctx.warn(err).await;
+    }
+
+    Ok(())
+}
+
+/// Joins the voice channel and starts a media player for the guild
+pub async fn connect(
+    ctx: &SerenityContext,
+    media_player: &Arc<GlobalMediaPlayer>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    message_ctx: MessageContext,
+) -> Result<(), String> {
+    let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
-    let handler = manager.join(guild_id, connect_to).await;
+    // Joining moves the bot even if it is already playing in another channel
+    if media_player.guild_ids().await.contains(&guild_id) {
+        return Err("Already connected to a voice channel in this server!".to_string());
+    }
 
-    if let Ok(_) = handler.1 {
-        let res = media_player.start(guild_id, handler.0).await;
-        match res {
-            Ok(_) => (),
-            Err(err) => {
-                ctx.warn(err).await;
-            }
+    // Left over from being disconnected by someone else, which would keep the bot from joining
+    if manager.get(guild_id).is_some() {
+        if let Err(err) = manager.remove(guild_id).await {
+            warn!("Unable to remove stale call of {}: {:?}", guild_id, err);
         }
     }
 
-    Ok(())
+    let (handler, join_result) = manager.join(guild_id, channel_id).await;
+
+    if let Err(err) = join_result {
+        return Err(format!("Unable to join channel: {:?}", err));
+    }
+
+    let metadata = get_metadata_provider(ctx).await.unwrap();
+
+    media_player
+        .start(guild_id, handler, message_ctx, metadata)
+        .await?;
+
+    let idle_watcher = tokio::spawn(inactivity::watch_idle(
+        ctx.clone(),
+        media_player.clone(),
+        guild_id,
+    ));
+
+    media_player.set_idle_watcher(guild_id, idle_watcher).await
+}
+
+/// Whether the user is in the same voice channel as the bot
+pub fn in_bot_channel(ctx: &SerenityContext, guild_id: GuildId, user_id: UserId) -> bool {
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+
+    let channel_of = |user_id| {
+        guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice_state| voice_state.channel_id)
+    };
+
+    let bot_channel = channel_of(ctx.cache.current_user_id());
+
+    bot_channel.is_some() && channel_of(user_id) == bot_channel
+}
+
+/// Counts the users other than bots in the bot's voice channel.
+///
+/// Returns None if the bot is not in a voice channel.
+pub fn listener_count(ctx: &SerenityContext, guild_id: GuildId) -> Option<usize> {
+    let guild = ctx.cache.guild(guild_id)?;
+
+    let bot_channel = guild
+        .voice_states
+        .get(&ctx.cache.current_user_id())
+        .and_then(|voice_state| voice_state.channel_id)?;
+
+    let count = guild
+        .voice_states
+        .values()
+        .filter(|voice_state| {
+            let is_bot = match &voice_state.member {
+                Some(member) => member.user.bot,
+                None => ctx
+                    .cache
+                    .user(voice_state.user_id)
+                    .map_or(false, |user| user.bot),
+            };
+
+            voice_state.channel_id == Some(bot_channel) && !is_bot
+        })
+        .count();
+
+    Some(count)
+}
+
+/// Stops the media player and disconnects from the voice channel
+pub async fn leave_channel(
+    manager: &Songbird,
+    media_player: &GlobalMediaPlayer,
+    guild_id: GuildId,
+) -> Result<(), String> {
+    let quit_result = media_player.quit(guild_id).await;
+
+    if let Err(err) = manager.remove(guild_id).await {
+        return Err(format!("Failed: {:?}", err));
+    }
+
+    quit_result
 }
@@ -0,0 +1,144 @@
+use poise::serenity_prelude::{Context as SerenityContext, GuildId, Member, RoleId};
+use tracing::{error, info};
+
+use crate::{
+    config, database::plugin::get_db_plugin, media::plugin::get_media_player,
+    utils::responses::Responses, Context, Error,
+};
+
+/// The guild's DJ role, set with the dj-role command or else DJ_ROLE_ID. Roles the guild
+/// doesn't have count as no DJ role, so a role of another server doesn't lock everyone out.
+pub async fn dj_role(ctx: &SerenityContext, guild_id: GuildId) -> Option<RoleId> {
+    let saved = match get_db_plugin(ctx).await.unwrap().get_dj_role(guild_id) {
+        Ok(role) => role,
+        Err(err) => {
+            error!("Unable to read DJ role of {}: {}", guild_id, err);
+            None
+        }
+    };
+
+    let role = saved.or_else(|| config::roles::dj(guild_id))?;
+
+    ctx.cache
+        .guild_field(guild_id, |guild| guild.roles.contains_key(&role))
+        .unwrap_or(false)
+        .then_some(role)
+}
+
+/// Returns true if the member has the guild's DJ role
+pub async fn is_dj(ctx: &SerenityContext, member: &Member) -> bool {
+    match dj_role(ctx, member.guild_id).await {
+        Some(role) => member.roles.contains(&role),
+        None => false,
+    }
+}
+
+/// Whether the member may use DJ only controls, which everyone may if there is no DJ role
+pub async fn has_dj_access(ctx: &SerenityContext, member: &Member) -> bool {
+    match dj_role(ctx, member.guild_id).await {
+        Some(role) => member.roles.contains(&role),
+        None => true,
+    }
+}
+
+async fn author_has_dj_access(ctx: Context<'_>) -> bool {
+    match ctx.author_member().await {
+        Some(member) => has_dj_access(ctx.discord(), &member).await,
+        None => false,
+    }
+}
+
+async fn author_requested_current(ctx: Context<'_>) -> bool {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return false,
+    };
+
+    match get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .now_playing(guild_id)
+        .await
+    {
+        Ok(Some(now_playing)) => now_playing.requester.user_id == ctx.author().id,
+        _ => false,
+    }
+}
+
+/// Command check that only lets DJs through. Everyone is allowed if no DJ role is set.
+pub async fn dj_only(ctx: Context<'_>) -> Result<bool, Error> {
+    if ctx.guild_id().is_none() || author_has_dj_access(ctx).await {
+        return Ok(true);
+    }
+
+    ctx.error("Only DJs can use this command").await;
+
+    Ok(false)
+}
+
+/// Command check that lets DJs through, and whoever queued the current song if
+/// DJ_ALLOW_REQUESTER is set.
+pub async fn dj_or_requester(ctx: Context<'_>) -> Result<bool, Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(true),
+    };
+
+    if author_has_dj_access(ctx).await {
+        return Ok(true);
+    }
+
+    if config::roles::allow_requester(guild_id) && author_requested_current(ctx).await {
+        return Ok(true);
+    }
+
+    ctx.error(if config::roles::allow_requester(guild_id) {
+        "Only DJs or whoever queued the current song can use this command"
+    } else {
+        "Only DJs can use this command"
+    })
+    .await;
+
+    Ok(false)
+}
+
+async fn author_is_admin(ctx: Context<'_>) -> bool {
+    match ctx.author_member().await {
+        Some(member) => member
+            .permissions(ctx.discord())
+            .map_or(false, |permissions| permissions.administrator()),
+        None => false,
+    }
+}
+
+/// Command check for debug commands, which users in OWNER_IDS and server administrators may
+/// run. Every attempt is written to the log under the "audit" target.
+pub async fn owner_or_admin(ctx: Context<'_>) -> Result<bool, Error> {
+    let author = ctx.author();
+    let allowed = config::owners().contains(&author.id) || author_is_admin(ctx).await;
+
+    info!(
+        target: "audit",
+        "{} ({}) {} command {} in {:?}",
+        author.name,
+        author.id,
+        if allowed { "used" } else { "was denied" },
+        ctx.command().qualified_name,
+        ctx.guild_id()
+    );
+
+    if !allowed {
+        ctx.error("Only bot owners and administrators can use this command")
+            .await;
+    }
+
+    Ok(allowed)
+}
+
+/// Command check for skip. Votes decide skips when vote skipping is on.
+pub async fn can_skip(ctx: Context<'_>) -> Result<bool, Error> {
+    match ctx.guild_id() {
+        Some(guild_id) if config::skip::vote_ratio(guild_id).is_some() => Ok(true),
+        _ => dj_or_requester(ctx).await,
+    }
+}
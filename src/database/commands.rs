@@ -1,12 +1,22 @@
 use std::{collections::LinkedList, sync::Arc};
 
-use poise::{command, serenity_prelude::CreateEmbed};
+use poise::{
+    command,
+    serenity_prelude::{CreateEmbed, GuildId, UserId},
+};
 use tracing::error;
 
 use crate::{
-    media::{self, media_info::MediaInfo, metadata},
+    media::{
+        self,
+        global_media_player::GlobalMediaPlayer,
+        media_info::{MediaInfo, PlaylistInfo, Requester},
+        metadata::{self, MetadataProvider, PlaylistItems},
+        plugin::get_metadata_provider,
+    },
     utils::{
         self, config,
+        message_context::MessageContext,
         responses::{self, Responses},
         strings, validate_page,
     },
@@ -48,7 +58,7 @@ async fn _list_playlists(ctx: Context<'_>, page: Option<i64>) -> CommandResult {
         Ok((
             response::print_playlists(
                 &mut CreateEmbed::default(),
-                &format!("{}'s playlists", ctx.author().name).to_string(),
+                &format!("{}'s playlists", ctx.author().name),
                 playlists,
                 page,
                 count,
@@ -69,72 +79,64 @@ async fn _list_playlists(ctx: Context<'_>, page: Option<i64>) -> CommandResult {
 async fn create_playlist(
     ctx: Context<'_>,
     #[description = "Playlist name or URL"] playlist: String,
+    #[description = "Songs to add from the URL, e.g. 1-20, 5,7,9 or last 10"]
+    #[rest]
+    items: Option<String>,
 ) -> CommandResult {
     ctx.defer().await.unwrap();
 
     let db = get_db(ctx).await?;
 
+    let items = match items.as_deref().map(PlaylistItems::parse).transpose() {
+        Ok(items) => items,
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
+
     if strings::is_url(&playlist) {
         // Url
-        if metadata::is_playlist(&playlist) {
-            let Ok(mut songs) = metadata::get_playlist(&playlist) else {
-                ctx.error("Couldn't retreive song from playlist").await;
-                error!("get_playlist error");
-
-                return Ok(());
-            };
-
-            // Playlist
-            if songs.len() <= 0 {
-                ctx.error("Playlist is empty!").await;
-
-                return Ok(());
-            }
+        let metadata_provider = get_metadata_provider(ctx.discord()).await.unwrap();
 
-            let first_song = songs.pop_front().unwrap();
-            let some_playlist_info = &first_song.playlist.clone();
-
-            let Some(playlist_info) = some_playlist_info else {
-                ctx.error("Couldn't retreive playlist data").await;
-                error!("get_playlist playlist info is empty");
-                return Ok(());
-            };
-
-            // Create playlist
-            if let Err(err) = db.create_playlist(ctx.author().id, &playlist_info.title) {
-                ctx.error("Failed to create playlist").await;
-                error!("{}", err);
+        let (playlist_info, len) = match import_playlist(
+            db.as_ref(),
+            metadata_provider.as_ref(),
+            ctx.author().id,
+            &playlist,
+            items.as_ref(),
+        )
+        .await
+        {
+            Ok(imported) => imported,
+            Err(err) => {
+                ctx.error(err).await;
                 return Ok(());
             }
-
-            // Add songs to playlist
-            songs.push_front(first_song);
-
-            let len = songs.len();
-
-            let _ = db.add_playlist_songs(
-                ctx.author().id,
-                &playlist_info.title,
-                songs.iter().collect::<Vec<&MediaInfo>>(),
-            );
-
-            ctx.send(|m| {
-                m.embed(|e| {
-                    e.title(format!(
-                        "Created a playlist: {} ({})",
-                        playlist_info.title,
-                        ctx.author().name
-                    ))
-                    .description(format!(
-                        "Uploader: **{}**\nTracks: **{}**",
-                        playlist_info.uploader, len
-                    ))
-                })
+        };
+
+        ctx.send(|m| {
+            m.embed(|e| {
+                e.title(format!(
+                    "Created a playlist: {} ({})",
+                    playlist_info.title,
+                    ctx.author().name
+                ))
+                .description(format!(
+                    "Uploader: **{}**\nTracks: **{}**{}",
+                    playlist_info.uploader,
+                    len,
+                    match &items {
+                        Some(items) => format!("\nItems: **{}**", items),
+                        None => "".to_string(),
+                    }
+                ))
             })
-            .await?;
-        } else {
-            ctx.error("URL given is not a playlist!").await;
-        }
+        })
+        .await?;
+    } else if items.is_some() {
+        ctx.error("Songs can only be picked from playlist URLs!")
+            .await;
     } else {
         // Name
         if let Err(err) = db.create_playlist(ctx.author().id, &playlist) {
@@ -161,30 +163,33 @@ async fn play_playlist(
         .await
         .unwrap();
 
-    let Ok(playlist) = db.get_playlist(ctx.author().id, &playlist_name, 9999, 0) else {
-        ctx.error("Unable to retreive songs from playlist").await;
-        return Ok(())
-    };
-
-    let songs = playlist.0;
-
     media::commands::check_or_join_vc(ctx).await?;
 
-    media_playlist
-        .enqueue_batch(
-            ctx.guild_id().unwrap(),
-            songs.into_iter().collect::<LinkedList<MediaInfo>>(),
-            ctx.into(),
-        )
-        .await?;
+    let count = match queue_playlist(
+        db.as_ref(),
+        &media_playlist,
+        ctx.guild_id().unwrap(),
+        &playlist_name,
+        ctx.into(),
+        Requester::from_author(ctx).await,
+    )
+    .await
+    {
+        Ok(count) => count,
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
 
     media::commands::response::playlist_response(
         ctx,
         &playlist_name,
         &ctx.author().name,
-        playlist.1,
-        &"".to_string(),
-        &"".to_string(),
+        count,
+        "",
+        "",
+        None,
     )
     .await;
 
@@ -374,6 +379,77 @@ async fn queue_history(
 
 // Helpers
 
+/// Saves the songs of a playlist url as a playlist of the user, named after the playlist.
+/// Returns the playlist's info and how many songs were saved.
+async fn import_playlist(
+    db: &dyn DatabasePlugin,
+    metadata_provider: &dyn MetadataProvider,
+    user_id: UserId,
+    url: &str,
+    items: Option<&PlaylistItems>,
+) -> Result<(PlaylistInfo, usize), String> {
+    if !metadata::is_playlist(metadata_provider, url).await {
+        return Err("URL given is not a playlist!".to_string());
+    }
+
+    let songs = match metadata_provider.get_playlist(url, items).await {
+        Ok(songs) => songs,
+        Err(err) => {
+            error!("get_playlist error: {}", err);
+            return Err("Couldn't retreive song from playlist".to_string());
+        }
+    };
+
+    let Some(first_song) = songs.front() else {
+        return Err("Playlist is empty!".to_string());
+    };
+
+    let Some(playlist_info) = first_song.playlist.clone() else {
+        error!("get_playlist playlist info is empty");
+        return Err("Couldn't retreive playlist data".to_string());
+    };
+
+    if let Err(err) = db.create_playlist(user_id, &playlist_info.title) {
+        error!("{}", err);
+        return Err("Failed to create playlist".to_string());
+    }
+
+    if let Err(err) = db.add_playlist_songs(
+        user_id,
+        &playlist_info.title,
+        songs.iter().collect::<Vec<&MediaInfo>>(),
+    ) {
+        error!("{}", err);
+    }
+
+    Ok((playlist_info, songs.len()))
+}
+
+/// Queues the songs of one of the requester's playlists. Returns how many songs the playlist has.
+async fn queue_playlist(
+    db: &dyn DatabasePlugin,
+    media_player: &GlobalMediaPlayer,
+    guild_id: GuildId,
+    playlist_name: &str,
+    message_ctx: MessageContext,
+    requester: Requester,
+) -> Result<usize, String> {
+    let Ok((songs, count)) = db.get_playlist(requester.user_id, playlist_name, 9999, 0) else {
+        return Err("Unable to retreive songs from playlist".to_string());
+    };
+
+    media_player
+        .enqueue_batch(
+            guild_id,
+            songs.into_iter().collect::<LinkedList<MediaInfo>>(),
+            message_ctx,
+            requester,
+        )
+        .await?;
+
+    Ok(count)
+}
+
 async fn get_db(ctx: Context<'_>) -> Result<Arc<dyn DatabasePlugin>, String> {
     let db = get_db_plugin(ctx.discord())
         .await
@@ -393,7 +469,7 @@ mod response {
 
     pub fn print_playlists<'a>(
         e: &'a mut CreateEmbed,
-        title: &String,
+        title: &str,
         playlists: Vec<String>,
         page: usize,
         total: usize,
@@ -420,3 +496,94 @@ mod response {
             .color(config::colors::playlist())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::sqlite_plugin::SQLitePlugin,
+        media::fake_metadata::{
+            mock_db, mock_media_player, mock_message_ctx, mock_requester, FakeMetadataProvider,
+            GUILD_ID, PLAYLIST_URL, VIDEO_URL,
+        },
+    };
+
+    async fn import(
+        db: &SQLitePlugin,
+        url: &str,
+        items: Option<&PlaylistItems>,
+    ) -> Result<(PlaylistInfo, usize), String> {
+        import_playlist(
+            db,
+            &FakeMetadataProvider::new(),
+            mock_requester().user_id,
+            url,
+            items,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn create_and_play_playlist() {
+        let db = mock_db("create-and-play");
+        let media_player = mock_media_player().await;
+
+        let (playlist_info, count) = import(&db, PLAYLIST_URL, None).await.unwrap();
+
+        assert_eq!(playlist_info.title, "80s Hits");
+        assert_eq!(count, 3);
+
+        let queued = queue_playlist(
+            &*db,
+            &media_player,
+            GUILD_ID,
+            &playlist_info.title,
+            mock_message_ctx(),
+            mock_requester(),
+        )
+        .await
+        .unwrap();
+
+        let (queue, _) = media_player.read_queue(GUILD_ID, 1, 10).await.unwrap();
+        let mut titles: Vec<String> = queue.into_iter().map(|(info, _)| info.title).collect();
+        titles.sort();
+
+        assert_eq!(queued, 3);
+        assert_eq!(
+            titles,
+            vec![
+                "Journey - Don't Stop Believin' (Official Audio)",
+                "Toto - Africa (Official HD Video)",
+                "a-ha - Take On Me (Official Video) [4K]",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_playlist_from_items() {
+        let db = mock_db("create-items");
+
+        let (playlist_info, count) = import(&db, PLAYLIST_URL, Some(&PlaylistItems::Last(2)))
+            .await
+            .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            db.get_playlist(mock_requester().user_id, &playlist_info.title, 10, 0)
+                .unwrap()
+                .1,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn create_playlist_from_song() {
+        let db = mock_db("create-song");
+
+        assert!(import(&db, VIDEO_URL, None).await.is_err());
+        assert_eq!(
+            db.get_playlists(mock_requester().user_id, 10, 0).unwrap().1,
+            0
+        );
+    }
+}
@@ -1,12 +1,16 @@
+use serde::{Deserialize, Serialize};
 use serenity::{
     client::{ClientBuilder, Context},
-    model::prelude::UserId,
+    model::prelude::{ChannelId, GuildId, RoleId, UserId},
     prelude::TypeMapKey,
 };
 
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, sync::Arc, time::Duration};
 
-use crate::media::media_info::MediaInfo;
+use crate::media::{
+    media_info::{MediaInfo, Requester},
+    metadata::SearchBackend,
+};
 
 pub struct DatabasePluginKey;
 
@@ -39,6 +43,26 @@ impl Display for DBError {
     }
 }
 
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SnapshotSong {
+    pub info: MediaInfo,
+    pub requester: Requester,
+}
+
+/// A guild's player state, saved on shutdown so it can be restored later
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub guild_id: GuildId,
+    pub voice_channel: ChannelId,
+    /// Channel notices were sent to
+    pub text_channel: ChannelId,
+    pub now_playing: Option<SnapshotSong>,
+    /// Position of the current song in seconds
+    pub position: i64,
+    /// Queued songs, next song first
+    pub queue: Vec<SnapshotSong>,
+}
+
 pub type PluginResult = Result<(), DBError>;
 pub type PluginDataResult = Result<(Vec<MediaInfo>, usize), DBError>;
 
@@ -51,13 +75,13 @@ pub trait DatabasePlugin: Sync + Send {
     /// Returns the history. Latest song is index 0.
     fn get_history(&self, user_id: UserId, amount: usize, offset: usize) -> PluginDataResult;
 
-    fn create_playlist(&self, user_id: UserId, name: &String) -> PluginResult;
-    fn delete_playlist(&self, user_id: UserId, name: &String) -> PluginResult;
+    fn create_playlist(&self, user_id: UserId, name: &str) -> PluginResult;
+    fn delete_playlist(&self, user_id: UserId, name: &str) -> PluginResult;
 
     fn get_playlist(
         &self,
         user_id: UserId,
-        name: &String,
+        name: &str,
         amount: usize,
         offset: usize,
     ) -> PluginDataResult;
@@ -69,20 +93,39 @@ pub trait DatabasePlugin: Sync + Send {
         offset: usize,
     ) -> Result<(Vec<String>, usize), DBError>;
 
-    fn search_playlists(
-        &self,
-        user_id: UserId,
-        search_term: &String,
-    ) -> Result<Vec<String>, DBError>;
+    fn search_playlists(&self, user_id: UserId, search_term: &str) -> Result<Vec<String>, DBError>;
 
     fn add_playlist_songs(
         &self,
         user_id: UserId,
-        name: &String,
+        name: &str,
         song: Vec<&MediaInfo>,
     ) -> PluginResult;
 
-    fn delete_playlist_song(&self, user_id: UserId, name: &String, url: &String) -> PluginResult;
+    fn delete_playlist_song(&self, user_id: UserId, name: &str, url: &str) -> PluginResult;
+
+    /// Saves a guild's queue, replacing any previous snapshot of it
+    fn save_queue_snapshot(&self, snapshot: &QueueSnapshot) -> PluginResult;
+    /// Returns every saved snapshot
+    fn get_queue_snapshots(&self) -> Result<Vec<QueueSnapshot>, DBError>;
+    fn delete_queue_snapshot(&self, guild_id: GuildId) -> PluginResult;
+
+    /// Returns the DJ role the guild picked, if any
+    fn get_dj_role(&self, guild_id: GuildId) -> Result<Option<RoleId>, DBError>;
+    /// Sets the guild's DJ role, or removes it if None
+    fn set_dj_role(&self, guild_id: GuildId, role_id: Option<RoleId>) -> PluginResult;
+
+    /// Returns the search backends the guild picked in order, or nothing if it didn't
+    fn get_search_backends(&self, guild_id: GuildId) -> Result<Vec<SearchBackend>, DBError>;
+    /// Sets the guild's search backends, or removes them if empty
+    fn set_search_backends(&self, guild_id: GuildId, backends: &[SearchBackend]) -> PluginResult;
+
+    /// Returns a cached value if it was stored less than `max_age` ago
+    fn get_cached(&self, key: &str, max_age: Duration) -> Result<Option<String>, DBError>;
+    /// Stores a value and removes the ones stored more than `max_age` ago
+    fn set_cached(&self, key: &str, value: &str, max_age: Duration) -> PluginResult;
+    /// Removes every cached value. Returns how many were removed.
+    fn purge_cache(&self) -> Result<usize, DBError>;
 }
 
 fn register_database_plugin(
@@ -1,12 +1,15 @@
-use std::env;
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use rusqlite::{named_params, params, Connection, OpenFlags};
-use serenity::model::prelude::UserId;
+use serenity::model::prelude::{GuildId, RoleId, UserId};
 use tracing::{error, info};
 
-use crate::media::media_info::MediaInfo;
+use crate::media::{media_info::MediaInfo, metadata::SearchBackend};
 
-use super::plugin::{DBError, DatabasePlugin};
+use super::plugin::{DBError, DatabasePlugin, QueueSnapshot};
 
 const HISTORY_PLAYLIST: &str = "_history";
 
@@ -46,7 +49,7 @@ impl SQLitePlugin {
     fn _get_playlist(
         &self,
         user_id: UserId,
-        name: &String,
+        name: &str,
         amount: usize,
         offset: usize,
         reverse: bool,
@@ -195,6 +198,23 @@ impl DatabasePlugin for SQLitePlugin {
                         FOREIGN KEY(song_url) REFERENCES songs(url)
                         ON DELETE CASCADE
                 );
+                CREATE TABLE IF NOT EXISTS queue_snapshots (
+                    guild_id INTEGER PRIMARY KEY,
+                    snapshot TEXT
+                );
+                CREATE TABLE IF NOT EXISTS dj_roles (
+                    guild_id INTEGER PRIMARY KEY,
+                    role_id INTEGER
+                );
+                CREATE TABLE IF NOT EXISTS search_backends (
+                    guild_id INTEGER PRIMARY KEY,
+                    backends TEXT
+                );
+                CREATE TABLE IF NOT EXISTS metadata_cache (
+                    key TEXT PRIMARY KEY,
+                    value TEXT,
+                    cached_at INTEGER
+                );
                 ",
             )
             .expect("[sqlite] Unable to init database");
@@ -204,7 +224,7 @@ impl DatabasePlugin for SQLitePlugin {
         self.is_disabled()
     }
 
-    fn create_playlist(&self, user_id: UserId, name: &String) -> Result<(), DBError> {
+    fn create_playlist(&self, user_id: UserId, name: &str) -> Result<(), DBError> {
         if self.is_disabled() {
             return Ok(());
         }
@@ -236,7 +256,7 @@ impl DatabasePlugin for SQLitePlugin {
         Ok(())
     }
 
-    fn delete_playlist(&self, user_id: UserId, name: &String) -> Result<(), DBError> {
+    fn delete_playlist(&self, user_id: UserId, name: &str) -> Result<(), DBError> {
         if self.is_disabled() {
             return Ok(());
         }
@@ -264,7 +284,7 @@ impl DatabasePlugin for SQLitePlugin {
     fn add_playlist_songs(
         &self,
         user_id: UserId,
-        name: &String,
+        name: &str,
         songs: Vec<&MediaInfo>,
     ) -> Result<(), DBError> {
         if self.is_disabled() {
@@ -333,12 +353,7 @@ impl DatabasePlugin for SQLitePlugin {
         Ok(())
     }
 
-    fn delete_playlist_song(
-        &self,
-        user_id: UserId,
-        name: &String,
-        url: &String,
-    ) -> Result<(), DBError> {
+    fn delete_playlist_song(&self, user_id: UserId, name: &str, url: &str) -> Result<(), DBError> {
         if self.is_disabled() {
             return Ok(());
         }
@@ -372,7 +387,7 @@ impl DatabasePlugin for SQLitePlugin {
     fn get_playlist(
         &self,
         user_id: UserId,
-        name: &String,
+        name: &str,
         amount: usize,
         offset: usize,
     ) -> Result<(Vec<MediaInfo>, usize), DBError> {
@@ -380,7 +395,7 @@ impl DatabasePlugin for SQLitePlugin {
     }
 
     fn set_history(&self, user_id: UserId, info: &MediaInfo) -> Result<(), DBError> {
-        self.add_playlist_songs(user_id, &HISTORY_PLAYLIST.to_string(), vec![info])
+        self.add_playlist_songs(user_id, HISTORY_PLAYLIST, vec![info])
     }
 
     fn get_history(
@@ -389,7 +404,7 @@ impl DatabasePlugin for SQLitePlugin {
         amount: usize,
         offset: usize,
     ) -> Result<(Vec<MediaInfo>, usize), DBError> {
-        self._get_playlist(user_id, &HISTORY_PLAYLIST.to_string(), amount, offset, true)
+        self._get_playlist(user_id, HISTORY_PLAYLIST, amount, offset, true)
     }
 
     fn get_playlists(
@@ -437,11 +452,7 @@ impl DatabasePlugin for SQLitePlugin {
         Ok((playlists, size as usize))
     }
 
-    fn search_playlists(
-        &self,
-        user_id: UserId,
-        search_term: &String,
-    ) -> Result<Vec<String>, DBError> {
+    fn search_playlists(&self, user_id: UserId, search_term: &str) -> Result<Vec<String>, DBError> {
         let connection = self.get_connection()?;
 
         // Get playlists
@@ -463,10 +474,225 @@ impl DatabasePlugin for SQLitePlugin {
 
         Ok(query.filter_map(|m| m.ok()).collect::<Vec<String>>())
     }
+
+    fn save_queue_snapshot(&self, snapshot: &QueueSnapshot) -> Result<(), DBError> {
+        if self.is_disabled() {
+            return Ok(());
+        }
+
+        let connection = self.get_connection()?;
+
+        let snapshot_json = match serde_json::to_string(snapshot) {
+            Ok(json) => json,
+            Err(err) => return Err(format!("Unable to serialize queue: {}", err).into()),
+        };
+
+        connection.execute(
+            "INSERT OR REPLACE INTO queue_snapshots VALUES (?1, ?2)",
+            params![snapshot.guild_id.as_u64(), snapshot_json],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_queue_snapshots(&self) -> Result<Vec<QueueSnapshot>, DBError> {
+        if self.is_disabled() {
+            return Ok(vec![]);
+        }
+
+        let connection = self.get_connection()?;
+
+        let mut statement = connection.prepare("SELECT snapshot FROM queue_snapshots")?;
+
+        let query = statement.query_map((), |r| r.get::<_, String>(0))?;
+
+        let snapshots = query
+            .filter_map(|m| m.ok())
+            .filter_map(|snapshot_json| {
+                match serde_json::from_str::<QueueSnapshot>(&snapshot_json) {
+                    Ok(snapshot) => Some(snapshot),
+                    Err(err) => {
+                        error!(
+                            "Unable to deserialize queue snapshot: {}. Error message: {}",
+                            snapshot_json, err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Ok(snapshots)
+    }
+
+    fn delete_queue_snapshot(&self, guild_id: GuildId) -> Result<(), DBError> {
+        if self.is_disabled() {
+            return Ok(());
+        }
+
+        let connection = self.get_connection()?;
+
+        connection.execute(
+            "DELETE FROM queue_snapshots WHERE guild_id = ?1",
+            params![guild_id.as_u64()],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_dj_role(&self, guild_id: GuildId) -> Result<Option<RoleId>, DBError> {
+        if self.is_disabled() {
+            return Ok(None);
+        }
+
+        let connection = self.get_connection()?;
+
+        let mut statement = connection.prepare("SELECT role_id FROM dj_roles WHERE guild_id=?1")?;
+
+        let role_id = match statement.query(params![guild_id.as_u64()])?.next()? {
+            Some(row) => Some(RoleId(row.get(0)?)),
+            None => None,
+        };
+
+        Ok(role_id)
+    }
+
+    fn set_dj_role(&self, guild_id: GuildId, role_id: Option<RoleId>) -> Result<(), DBError> {
+        if self.is_disabled() {
+            return Err("Server settings can't be saved without a database".into());
+        }
+
+        let connection = self.get_connection()?;
+
+        match role_id {
+            Some(role_id) => connection.execute(
+                "INSERT OR REPLACE INTO dj_roles VALUES (?1, ?2)",
+                params![guild_id.as_u64(), role_id.as_u64()],
+            )?,
+            None => connection.execute(
+                "DELETE FROM dj_roles WHERE guild_id=?1",
+                params![guild_id.as_u64()],
+            )?,
+        };
+
+        Ok(())
+    }
+
+    fn get_search_backends(&self, guild_id: GuildId) -> Result<Vec<SearchBackend>, DBError> {
+        if self.is_disabled() {
+            return Ok(vec![]);
+        }
+
+        let connection = self.get_connection()?;
+
+        let mut statement =
+            connection.prepare("SELECT backends FROM search_backends WHERE guild_id=?1")?;
+
+        let backends: String = match statement.query(params![guild_id.as_u64()])?.next()? {
+            Some(row) => row.get(0)?,
+            None => return Ok(vec![]),
+        };
+
+        Ok(backends
+            .split(',')
+            .filter_map(SearchBackend::parse)
+            .collect())
+    }
+
+    fn set_search_backends(
+        &self,
+        guild_id: GuildId,
+        backends: &[SearchBackend],
+    ) -> Result<(), DBError> {
+        if self.is_disabled() {
+            return Err("Server settings can't be saved without a database".into());
+        }
+
+        let connection = self.get_connection()?;
+
+        if backends.is_empty() {
+            connection.execute(
+                "DELETE FROM search_backends WHERE guild_id=?1",
+                params![guild_id.as_u64()],
+            )?;
+        } else {
+            let names: Vec<&str> = backends.iter().map(|backend| backend.name()).collect();
+
+            connection.execute(
+                "INSERT OR REPLACE INTO search_backends VALUES (?1, ?2)",
+                params![guild_id.as_u64(), names.join(",")],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn get_cached(&self, key: &str, max_age: Duration) -> Result<Option<String>, DBError> {
+        if self.is_disabled() {
+            return Ok(None);
+        }
+
+        let connection = self.get_connection()?;
+
+        let mut statement = connection
+            .prepare("SELECT value FROM metadata_cache WHERE key=?1 AND cached_at>=?2")?;
+
+        let oldest = unix_time() - max_age.as_secs() as i64;
+
+        let value = match statement.query(params![key, oldest])?.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        };
+
+        Ok(value)
+    }
+
+    fn set_cached(&self, key: &str, value: &str, max_age: Duration) -> Result<(), DBError> {
+        if self.is_disabled() {
+            return Ok(());
+        }
+
+        let connection = self.get_connection()?;
+
+        let oldest = unix_time() - max_age.as_secs() as i64;
+
+        connection.execute(
+            "DELETE FROM metadata_cache WHERE cached_at<?1",
+            params![oldest],
+        )?;
+        connection.execute(
+            "INSERT OR REPLACE INTO metadata_cache VALUES (?1, ?2, ?3)",
+            params![key, value, unix_time()],
+        )?;
+
+        Ok(())
+    }
+
+    fn purge_cache(&self) -> Result<usize, DBError> {
+        if self.is_disabled() {
+            return Ok(0);
+        }
+
+        let connection = self.get_connection()?;
+
+        Ok(connection.execute("DELETE FROM metadata_cache", ())?)
+    }
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64)
 }
 
 #[cfg(test)]
 mod tests {
+    use serenity::model::prelude::ChannelId;
+
+    use crate::{
+        database::plugin::SnapshotSong,
+        media::media_info::{MediaSource, Requester},
+    };
     use serial_test::serial;
 
     use super::*;
@@ -528,6 +754,9 @@ mod tests {
             thumbnail: "".to_string(),
             uploader: "".to_string(),
             playlist: None,
+            partial: false,
+            source: MediaSource::YoutubeDL,
+            live: false,
         };
 
         let result = db.add_playlist_songs(user_id, &playlist, vec![&song]);
@@ -554,6 +783,9 @@ mod tests {
             thumbnail: "".to_string(),
             uploader: "".to_string(),
             playlist: None,
+            partial: false,
+            source: MediaSource::YoutubeDL,
+            live: false,
         };
 
         let result = db.add_playlist_songs(user_id, &playlist, vec![&song]);
@@ -699,6 +931,83 @@ mod tests {
         assert_eq!(songs.len(), 2);
     }
 
+    fn mock_song(url: &str) -> SnapshotSong {
+        SnapshotSong {
+            info: mock_info(url),
+            requester: Requester {
+                user_id: UserId(1),
+                name: "user".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn queue_snapshots_are_kept_until_deleted() {
+        let db = mock_db_plugin();
+
+        let snapshot = QueueSnapshot {
+            guild_id: GuildId(1),
+            voice_channel: ChannelId(2),
+            text_channel: ChannelId(3),
+            now_playing: Some(mock_song("url1")),
+            position: 42,
+            queue: vec![mock_song("url2"), mock_song("url3")],
+        };
+
+        db.save_queue_snapshot(&snapshot).unwrap();
+        db.save_queue_snapshot(&snapshot).unwrap();
+
+        assert_eq!(db.get_queue_snapshots().unwrap(), vec![snapshot.clone()]);
+        assert_eq!(db.get_queue_snapshots().unwrap(), vec![snapshot]);
+
+        db.delete_queue_snapshot(GuildId(1)).unwrap();
+
+        assert!(db.get_queue_snapshots().unwrap().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn dj_roles_are_per_guild() {
+        let db = mock_db_plugin();
+
+        db.set_dj_role(GuildId(1), Some(RoleId(10))).unwrap();
+        db.set_dj_role(GuildId(2), Some(RoleId(20))).unwrap();
+        db.set_dj_role(GuildId(1), Some(RoleId(11))).unwrap();
+
+        assert_eq!(db.get_dj_role(GuildId(1)).unwrap(), Some(RoleId(11)));
+        assert_eq!(db.get_dj_role(GuildId(2)).unwrap(), Some(RoleId(20)));
+
+        db.set_dj_role(GuildId(1), None).unwrap();
+
+        assert_eq!(db.get_dj_role(GuildId(1)).unwrap(), None);
+        assert_eq!(db.get_dj_role(GuildId(2)).unwrap(), Some(RoleId(20)));
+    }
+
+    #[test]
+    #[serial]
+    fn search_backends_are_per_guild() {
+        let db = mock_db_plugin();
+        let backends = [SearchBackend::SoundCloud, SearchBackend::YouTube];
+
+        assert!(db.get_search_backends(GuildId(1)).unwrap().is_empty());
+
+        db.set_search_backends(GuildId(1), &backends).unwrap();
+        db.set_search_backends(GuildId(2), &[SearchBackend::NicoNico])
+            .unwrap();
+
+        assert_eq!(db.get_search_backends(GuildId(1)).unwrap(), backends);
+        assert_eq!(
+            db.get_search_backends(GuildId(2)).unwrap(),
+            vec![SearchBackend::NicoNico]
+        );
+
+        db.set_search_backends(GuildId(1), &[]).unwrap();
+
+        assert!(db.get_search_backends(GuildId(1)).unwrap().is_empty());
+        assert_eq!(db.get_search_backends(GuildId(2)).unwrap().len(), 1);
+    }
+
     #[test]
     #[serial]
     fn delete_playlist() -> rusqlite::Result<()> {
@@ -747,4 +1056,68 @@ mod tests {
         // Count
         assert_eq!(playlists.1, 3);
     }
+
+    #[test]
+    #[serial]
+    fn metadata_cache() {
+        let db = mock_db_plugin();
+        let key = "url:test".to_string();
+
+        assert_eq!(db.get_cached(&key, Duration::from_secs(60)).unwrap(), None);
+
+        db.set_cached(&key, "value", Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(
+            db.get_cached(&key, Duration::from_secs(60)).unwrap(),
+            Some("value".to_string())
+        );
+        assert_eq!(db.purge_cache().unwrap(), 1);
+        assert_eq!(db.get_cached(&key, Duration::from_secs(60)).unwrap(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn metadata_cache_expires() {
+        let db = mock_db_plugin();
+        let key = "url:test".to_string();
+
+        db.get_connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO metadata_cache VALUES (?1, 'value', ?2)",
+                params![key, unix_time() - 120],
+            )
+            .unwrap();
+
+        assert_eq!(db.get_cached(&key, Duration::from_secs(60)).unwrap(), None);
+        assert!(db
+            .get_cached(&key, Duration::from_secs(180))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    #[serial]
+    fn metadata_cache_prunes_expired() {
+        let db = mock_db_plugin();
+        let old_key = "url:old".to_string();
+
+        db.get_connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO metadata_cache VALUES (?1, 'value', ?2)",
+                params![old_key, unix_time() - 120],
+            )
+            .unwrap();
+
+        db.set_cached("url:new", "value", Duration::from_secs(60))
+            .unwrap();
+
+        assert!(db
+            .get_cached(&old_key, Duration::from_secs(180))
+            .unwrap()
+            .is_none());
+        assert_eq!(db.purge_cache().unwrap(), 1);
+    }
 }
@@ -3,10 +3,12 @@ use std::path::Path;
 use poise::command;
 use serenity::model::prelude::AttachmentType;
 
-use crate::{utils::responses::Responses, CommandResult, Context};
+use crate::{
+    controls::permissions::owner_or_admin, utils::responses::Responses, CommandResult, Context,
+};
 
 /// Print the log. Use --help to see args
-#[command(prefix_command, category = "debug")]
+#[command(prefix_command, check = "owner_or_admin", category = "debug")]
 pub async fn log(ctx: Context<'_>, #[rest] args: Option<String>) -> CommandResult {
     let mut level = "".to_string();
     let mut target = "".to_string();
@@ -55,7 +57,12 @@ pub async fn log(ctx: Context<'_>, #[rest] args: Option<String>) -> CommandResul
 }
 
 /// Get the log file
-#[command(prefix_command, rename = "log-file", category = "debug")]
+#[command(
+    prefix_command,
+    rename = "log-file",
+    check = "owner_or_admin",
+    category = "debug"
+)]
 pub async fn log_file(ctx: Context<'_>) -> CommandResult {
     let log_file = super::get_log_filename();
 
@@ -5,15 +5,22 @@ mod media;
 mod utils;
 
 use dotenv::dotenv;
-use media::{global_media_player::GlobalMediaPlayer, plugin::GlobalMediaPlayerPluginInit};
+use media::{
+    global_media_player::GlobalMediaPlayer,
+    metadata::YoutubeDLProvider,
+    metadata_cache::CachedMetadataProvider,
+    plugin::{GlobalMediaPlayerPluginInit, MetadataCacheInit},
+};
 use poise::{command, serenity_prelude as serenity};
 use songbird::SerenityInit;
 use std::{env, sync::Arc};
+use tokio::signal;
 use tracing::info;
 use tracing_subscriber::{fmt, layer::SubscriberExt};
 use utils::{config, message_context};
 
 use crate::{
+    controls::permissions::owner_or_admin,
     database::{plugin::DatabasePluginInit, sqlite_plugin::SQLitePlugin},
     utils::responses::Responses,
 };
@@ -42,6 +49,11 @@ async fn main() {
     let db_plugin = Arc::new(SQLitePlugin::default());
     let media_player_plugin = Arc::new(GlobalMediaPlayer::UNINITIALIZED);
     media_player_plugin.init_self().await;
+    media::audio_cache::clear_partial();
+    let metadata_cache = Arc::new(CachedMetadataProvider::new(
+        Arc::new(YoutubeDLProvider),
+        db_plugin.clone(),
+    ));
 
     let intents =
         serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
@@ -51,6 +63,7 @@ async fn main() {
             c.register_songbird()
                 .register_database_plugin(db_plugin)
                 .register_media_player_plugin(media_player_plugin)
+                .register_metadata_cache(metadata_cache)
         })
         .token(std::env::var("DISCORD_TOKEN").expect("missing DISCORD_TOKEN"))
         .user_data_setup(move |ctx, _ready, framework| {
@@ -98,6 +111,9 @@ async fn main() {
                         .await
                         .expect("Failed to send startup message");
                 }
+                // Queues saved on the last shutdown
+                media::snapshots::offer_restore(ctx).await;
+
                 Ok(UserData {})
             })
         })
@@ -106,10 +122,28 @@ async fn main() {
                 media::commands::play(),
                 media::commands::play_single(),
                 media::commands::play_next(),
+                media::commands::search(),
+                media::commands::search_backends(),
+                media::commands::library(),
                 media::commands::seek(),
                 media::commands::skip(),
                 media::commands::queue(),
+                media::commands::previous(),
+                media::commands::replay(),
+                media::commands::recent(),
+                media::commands::pause(),
+                media::commands::resume(),
+                media::commands::volume(),
+                media::commands::normalize(),
+                media::commands::filter(),
                 media::commands::clear(),
+                media::commands::remove(),
+                media::commands::move_item(),
+                media::commands::swap(),
+                media::commands::shuffle(),
+                media::commands::shuffle_mode(),
+                media::commands::fair_mode(),
+                media::commands::loop_mode(),
                 media::commands::now_playing(),
                 media::commands::timestamp(),
                 database::commands::history(),
@@ -120,13 +154,25 @@ async fn main() {
                 controls::commands::unmute(),
                 controls::commands::deafen(),
                 controls::commands::undeafen(),
+                controls::commands::dj_role(),
                 logging::commands::log(),
                 logging::commands::log_file(),
+                media::commands::cache(),
+                media::commands::audio_cache(),
                 update(),
                 help(),
                 version(),
                 register(),
             ],
+            listener: |ctx, event, _framework, _user_data| {
+                Box::pin(async move {
+                    if let poise::Event::VoiceStateUpdate { new, .. } = event {
+                        controls::inactivity::handle_voice_state_update(ctx, new).await;
+                    }
+
+                    Ok(())
+                })
+            },
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: env::var("BOT_PREFIX").ok(),
                 ..Default::default()
@@ -135,7 +181,28 @@ async fn main() {
         })
         .intents(intents);
 
-    framework.run().await.unwrap();
+    let framework = framework.build().await.unwrap();
+
+    // Graceful shutdown
+    let shard_manager = framework.shard_manager().clone();
+    let data = framework.client().data.clone();
+    tokio::spawn(async move {
+        let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Unable to listen for SIGTERM");
+
+        tokio::select! {
+            _ = sigterm.recv() => (),
+            _ = signal::ctrl_c() => (),
+        }
+
+        info!("Shutting down");
+
+        media::snapshots::save_snapshots(&data).await;
+
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
+    framework.start().await.unwrap();
 }
 
 // Misc and tools
@@ -148,15 +215,31 @@ async fn version(ctx: Context<'_>) -> CommandResult {
     Ok(())
 }
 
-#[command(prefix_command, category = "debug")]
+#[command(prefix_command, check = "owner_or_admin", category = "debug")]
 async fn update(ctx: Context<'_>) -> CommandResult {
     // spawn yt-dlp --update
-    let cmd = std::process::Command::new("yt-dlp")
+    let cmd = match tokio::process::Command::new("yt-dlp")
         .arg("--update")
         .output()
-        .expect("failed to execute process");
+        .await
+    {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            ctx.error(format!("Failed to run yt-dlp: {}", err)).await;
+            return Ok(());
+        }
+    };
+
+    if !cmd.status.success() {
+        ctx.error(format!(
+            "yt-dlp update failed: {}",
+            String::from_utf8_lossy(&cmd.stderr)
+        ))
+        .await;
+        return Ok(());
+    }
 
-    ctx.send(|m| m.content(String::from_utf8(cmd.stdout).unwrap()))
+    ctx.send(|m| m.content(String::from_utf8_lossy(&cmd.stdout)))
         .await
         .expect("Failed to send message");
 
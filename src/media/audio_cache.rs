@@ -0,0 +1,454 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::process;
+use tracing::{error, info};
+
+use crate::config;
+
+use super::{media_info::MediaInfo, metadata::YOUTUBE_DL_COMMAND};
+
+const EXTENSION: &str = "opus";
+/// Where files are downloaded to before being moved into the cache
+const PARTIAL_DIR: &str = ".partial";
+/// Last time each cached file was played, as unix seconds
+const INDEX_FILE: &str = "played.json";
+
+/// How long a download waits after its song finished, so that it doesn't compete with the
+/// next song's youtube-dl starting up
+const DOWNLOAD_DELAY: Duration = Duration::from_secs(10);
+
+/// Set while a song is being downloaded. Only one is downloaded at a time.
+static DOWNLOADING: AtomicBool = AtomicBool::new(false);
+/// Held while the index is read and written
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+pub struct AudioCacheUsage {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// File name of a url's cached audio, from a hash that doesn't change between builds
+fn file_name(url: &str) -> String {
+    // FNV-1a
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{:016x}.{}", hash, EXTENSION)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns the cached audio of the url, if it was cached, and marks it as played in the
+/// background, so songs don't wait on the index to start
+pub fn lookup(url: &str) -> Option<PathBuf> {
+    let dir = config::cache::audio_dir()?;
+    let name = file_name(url);
+    let path = dir.join(&name);
+
+    if !path.is_file() {
+        return None;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = mark_played(&dir, name) {
+            error!("[audio cache] Unable to update index: {}", err);
+        }
+    });
+
+    Some(path)
+}
+
+/// Whether a song of this many seconds is short enough to cache. Unknown durations are not.
+fn fits(duration: i64, max_length: Duration) -> bool {
+    duration > 0 && duration as u64 <= max_length.as_secs()
+}
+
+/// Downloads the song's audio into the cache in the background, then evicts the least recently
+/// played files over the size cap. Does nothing if the cache is disabled or the song is too long.
+///
+/// The song is fetched again rather than saved while it streams, since seeks and filters change
+/// what is streamed. To keep the extra traffic down, songs finishing while another is being
+/// downloaded are skipped, and get cached the next time they play through.
+pub fn store(info: &MediaInfo) {
+    let Some(dir) = config::cache::audio_dir() else {
+        return;
+    };
+
+    if !fits(info.duration, config::cache::audio_max_length()) {
+        return;
+    }
+
+    let url = info.url.clone();
+
+    if dir.join(file_name(&url)).is_file() {
+        return;
+    }
+
+    if DOWNLOADING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        tokio::time::sleep(DOWNLOAD_DELAY).await;
+
+        let result = download(&dir, &url).await;
+
+        DOWNLOADING.store(false, Ordering::Release);
+
+        if let Err(err) = result {
+            error!("[audio cache] Unable to store {}: {}", url, err);
+            return;
+        }
+
+        info!("[audio cache] Stored {}", url);
+
+        let evicted =
+            tokio::task::spawn_blocking(move || evict(&dir, config::cache::audio_size())).await;
+
+        match evicted {
+            Ok(Ok(0)) => (),
+            Ok(Ok(count)) => info!("[audio cache] Evicted {} files", count),
+            Ok(Err(err)) => error!("[audio cache] Unable to evict files: {}", err),
+            Err(err) => error!("[audio cache] Eviction panicked: {}", err),
+        }
+    });
+}
+
+/// Has youtube-dl transcode the audio to opus, then moves it into the cache once complete.
+/// Whatever was written of it is removed if it fails.
+async fn download(dir: &Path, url: &str) -> Result<(), String> {
+    let name = file_name(url);
+    let partial_dir = dir.join(PARTIAL_DIR);
+
+    let result = run_youtube_dl(&partial_dir, url, &name)
+        .await
+        .and_then(|()| {
+            fs::rename(partial_dir.join(&name), dir.join(&name)).map_err(|err| err.to_string())
+        });
+
+    if let Err(err) = result {
+        if let Err(remove_err) = remove_partial(&partial_dir, &name) {
+            error!(
+                "[audio cache] Unable to remove partial {}: {}",
+                name, remove_err
+            );
+        }
+        return Err(err);
+    }
+
+    mark_played(dir, name).map_err(|err| err.to_string())
+}
+
+/// Downloads into the partial directory. youtube-dl is killed if it doesn't finish within the
+/// download timeout.
+async fn run_youtube_dl(partial_dir: &Path, url: &str, name: &str) -> Result<(), String> {
+    let output_template = partial_dir.join(Path::new(name).with_extension("%(ext)s"));
+
+    let child = process::Command::new(YOUTUBE_DL_COMMAND)
+        .args([
+            "-f",
+            "bestaudio/best",
+            "-x",
+            "--audio-format",
+            EXTENSION,
+            "--no-playlist",
+            "--ignore-config",
+            "--no-warnings",
+            "-o",
+        ])
+        .arg(&output_template)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    let timeout = config::timeouts::download();
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output.map_err(|err| err.to_string())?,
+        Err(_) => {
+            return Err(format!(
+                "Downloading took longer than {} seconds",
+                timeout.as_secs()
+            ))
+        }
+    };
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(())
+}
+
+/// Removes the partial file of a download, and youtube-dl's intermediate files like
+/// `<hash>.webm.part`
+fn remove_partial(partial_dir: &Path, name: &str) -> io::Result<()> {
+    let prefix = format!("{}.", name.trim_end_matches(&format!(".{}", EXTENSION)));
+
+    let entries = match fs::read_dir(partial_dir) {
+        Ok(entries) => entries,
+        // youtube-dl didn't get to write anything
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes downloads that were cut short by a restart. Does nothing if the cache is disabled.
+pub fn clear_partial() {
+    let Some(dir) = config::cache::audio_dir() else {
+        return;
+    };
+
+    match fs::remove_dir_all(dir.join(PARTIAL_DIR)) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => error!("[audio cache] Unable to clear partial downloads: {}", err),
+    }
+}
+
+fn read_index(dir: &Path) -> HashMap<String, u64> {
+    fs::read_to_string(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn write_index(dir: &Path, index: &HashMap<String, u64>) -> io::Result<()> {
+    fs::write(dir.join(INDEX_FILE), serde_json::to_string(index)?)
+}
+
+fn mark_played(dir: &Path, name: String) -> io::Result<()> {
+    let _lock = INDEX_LOCK.lock().unwrap();
+
+    let mut index = read_index(dir);
+    index.insert(name, unix_time());
+    write_index(dir, &index)
+}
+
+/// Cached files and their sizes
+fn cached_files(dir: &Path) -> io::Result<Vec<(String, u64)>> {
+    let mut files = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_file() && path.extension().map_or(false, |ext| ext == EXTENSION) {
+            files.push((
+                entry.file_name().to_string_lossy().to_string(),
+                entry.metadata()?.len(),
+            ));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Picks the least recently played files to remove until the rest fit in `max_size` bytes.
+/// Files missing from the index are picked first.
+fn files_to_evict(
+    mut files: Vec<(String, u64)>,
+    index: &HashMap<String, u64>,
+    max_size: u64,
+) -> Vec<String> {
+    let mut size: u64 = files.iter().map(|(_, bytes)| bytes).sum();
+
+    files.sort_by_key(|(name, _)| index.get(name).copied().unwrap_or_default());
+
+    files
+        .into_iter()
+        .take_while(|(_, bytes)| {
+            let over = size > max_size;
+            size -= bytes;
+            over
+        })
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Removes the least recently played files over the size cap. Returns how many were removed.
+fn evict(dir: &Path, max_size: u64) -> io::Result<usize> {
+    let _lock = INDEX_LOCK.lock().unwrap();
+
+    let mut index = read_index(dir);
+    let evicted = files_to_evict(cached_files(dir)?, &index, max_size);
+
+    for name in &evicted {
+        fs::remove_file(dir.join(name))?;
+        index.remove(name);
+    }
+
+    // also forget files that were removed by hand
+    let files = cached_files(dir)?;
+    index.retain(|name, _| files.iter().any(|(file, _)| file == name));
+
+    write_index(dir, &index)?;
+
+    Ok(evicted.len())
+}
+
+/// Size of the cache, or None if it is disabled
+pub fn usage() -> Option<Result<AudioCacheUsage, String>> {
+    let dir = config::cache::audio_dir()?;
+
+    let usage = match cached_files(&dir) {
+        Ok(files) => Ok(AudioCacheUsage {
+            files: files.len(),
+            bytes: files.iter().map(|(_, bytes)| bytes).sum(),
+        }),
+        // nothing was cached yet
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Ok(AudioCacheUsage { files: 0, bytes: 0 })
+        }
+        Err(err) => Err(err.to_string()),
+    };
+
+    Some(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, bytes: u64) -> (String, u64) {
+        (name.to_string(), bytes)
+    }
+
+    #[test]
+    fn file_name_is_stable() {
+        assert_eq!(
+            file_name("https://www.youtube.com/watch?v=6YBDo5S8soo"),
+            file_name("https://www.youtube.com/watch?v=6YBDo5S8soo")
+        );
+        assert_ne!(
+            file_name("https://www.youtube.com/watch?v=6YBDo5S8soo"),
+            file_name("https://www.youtube.com/watch?v=nBpgoga0FZ4")
+        );
+        assert_eq!(file_name(""), "cbf29ce484222325.opus");
+    }
+
+    #[test]
+    fn removes_partial_files_of_download() {
+        let dir = std::env::temp_dir().join(format!(
+            "mimicri-audio-cache-partial-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let name = file_name("a");
+        let other = file_name("b");
+        let stem = name.trim_end_matches(".opus");
+        for file in [
+            name.clone(),
+            format!("{}.webm.part", stem),
+            format!("{}.webm", stem),
+            other.clone(),
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        remove_partial(&dir, &name).unwrap();
+
+        let left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(left, vec![other]);
+    }
+
+    #[test]
+    fn only_fits_known_short_durations() {
+        let max_length = Duration::from_secs(30 * 60);
+
+        assert!(fits(212, max_length));
+        assert!(fits(30 * 60, max_length));
+        assert!(!fits(30 * 60 + 1, max_length));
+        assert!(!fits(0, max_length));
+    }
+
+    #[test]
+    fn evicts_least_recently_played() {
+        let files = vec![file("a", 10), file("b", 10), file("c", 10)];
+        let index = HashMap::from([
+            ("a".to_string(), 3),
+            ("b".to_string(), 1),
+            ("c".to_string(), 2),
+        ]);
+
+        assert_eq!(
+            files_to_evict(files.clone(), &index, 30),
+            Vec::<String>::new()
+        );
+        assert_eq!(files_to_evict(files.clone(), &index, 25), vec!["b"]);
+        assert_eq!(files_to_evict(files.clone(), &index, 10), vec!["b", "c"]);
+        assert_eq!(files_to_evict(files, &index, 0), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn evicts_unindexed_first() {
+        let files = vec![file("a", 10), file("b", 10)];
+        let index = HashMap::from([("a".to_string(), 1)]);
+
+        assert_eq!(files_to_evict(files, &index, 15), vec!["b"]);
+    }
+
+    #[test]
+    fn evict_removes_files() {
+        let dir =
+            std::env::temp_dir().join(format!("mimicri-audio-cache-evict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("old.opus"), [0; 10]).unwrap();
+        fs::write(dir.join("new.opus"), [0; 10]).unwrap();
+        write_index(
+            &dir,
+            &HashMap::from([
+                ("old.opus".to_string(), 1),
+                ("new.opus".to_string(), 2),
+                ("gone.opus".to_string(), 3),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(evict(&dir, 15).unwrap(), 1);
+
+        assert!(!dir.join("old.opus").exists());
+        assert!(dir.join("new.opus").exists());
+        assert_eq!(
+            read_index(&dir),
+            HashMap::from([("new.opus".to_string(), 2)])
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use poise::serenity_prelude::async_trait;
+use songbird::input::{
+    children_to_reader,
+    error::{Error, Result},
+    restartable::Restart,
+    Codec, Container, Input, Metadata, Restartable,
+};
+use std::{
+    ffi::OsString,
+    io,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use super::{
+    audio_cache, library,
+    media_info::{MediaInfo, MediaSource},
+    metadata::YOUTUBE_DL_COMMAND,
+    probe::{self, PIPE_PROTOCOLS},
+};
+
+/// ffmpeg filter used by the volume normalization mode
+pub const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// Effect presets that can be applied to a guild's tracks
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AudioFilter {
+    #[name = "bassboost"]
+    BassBoost,
+    #[name = "nightcore"]
+    Nightcore,
+    #[name = "vaporwave"]
+    Vaporwave,
+    #[name = "8d"]
+    EightD,
+}
+
+impl AudioFilter {
+    pub fn ffmpeg_filter(&self) -> &'static str {
+        match self {
+            AudioFilter::BassBoost => "bass=g=10",
+            AudioFilter::Nightcore => "aresample=48000,asetrate=48000*1.25,aresample=48000",
+            AudioFilter::Vaporwave => "aresample=48000,asetrate=48000*0.8,aresample=48000",
+            AudioFilter::EightD => "apulsator=hz=0.125",
+        }
+    }
+
+    /// How much faster the song plays with the filter, from its `asetrate`
+    pub fn speed(&self) -> f64 {
+        match self {
+            AudioFilter::Nightcore => 1.25,
+            AudioFilter::Vaporwave => 0.8,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Creates the source of a song. Songs from youtube-dl are played from the audio cache if they
+/// were cached.
+pub async fn create(info: &MediaInfo, filters: Vec<String>, lazy: bool) -> Result<Restartable> {
+    match info.source {
+        MediaSource::YoutubeDL => match audio_cache::lookup(&info.url) {
+            Some(path) => ffmpeg(path, filters, lazy).await,
+            None => ytdl(info.url.clone(), filters, lazy).await,
+        },
+        MediaSource::Library => match library::path_of(&info.url) {
+            Some(path) => ffmpeg(path, filters, lazy).await,
+            None => Err(Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in the music library", info.url),
+            ))),
+        },
+        MediaSource::Direct => url(info.url.clone(), filters, lazy).await,
+    }
+}
+
+/// Creates a restartable youtube-dl source, passing the audio filters to ffmpeg.
+///
+/// Falls back to songbird's own source if there are no filters. Lazy sources are only
+/// started once read or seeked, which avoids downloading from the start when seeking.
+pub async fn ytdl(url: String, filters: Vec<String>, lazy: bool) -> Result<Restartable> {
+    if filters.is_empty() {
+        Restartable::ytdl(url, lazy).await
+    } else {
+        Restartable::new(
+            FilteredRestarter {
+                source: FilteredSource::Ytdl(url),
+                filters: filters.join(","),
+            },
+            lazy,
+        )
+        .await
+    }
+}
+
+/// Creates a restartable ffmpeg source from a file, passing the audio filters to ffmpeg
+pub async fn ffmpeg(
+    input: impl Into<OsString>,
+    filters: Vec<String>,
+    lazy: bool,
+) -> Result<Restartable> {
+    if filters.is_empty() {
+        Restartable::ffmpeg(input.into(), lazy).await
+    } else {
+        Restartable::new(
+            FilteredRestarter {
+                source: FilteredSource::Ffmpeg(input.into()),
+                filters: filters.join(","),
+            },
+            lazy,
+        )
+        .await
+    }
+}
+
+/// Creates a restartable ffmpeg source from a url, which is opened with `probe::open` and piped
+/// to ffmpeg. Its redirects are followed and checked every time ffmpeg is started, including seeks.
+pub async fn url(url: String, filters: Vec<String>, lazy: bool) -> Result<Restartable> {
+    Restartable::new(
+        FilteredRestarter {
+            source: FilteredSource::Url(url),
+            filters: filters.join(","),
+        },
+        lazy,
+    )
+    .await
+}
+
+enum FilteredSource {
+    /// Piped from youtube-dl to ffmpeg
+    Ytdl(String),
+    /// Read by ffmpeg directly, a file path
+    Ffmpeg(OsString),
+    /// Opened by the bot and piped to ffmpeg, which is limited to the protocols of
+    /// `PIPE_PROTOCOLS`
+    Url(String),
+}
+
+/// Same as songbird's ytdl and ffmpeg restarters, but with an `-af` argument for ffmpeg.
+/// Without filters, `-af` is left out.
+struct FilteredRestarter {
+    source: FilteredSource,
+    filters: String,
+}
+
+#[async_trait]
+impl Restart for FilteredRestarter {
+    async fn call_restart(&mut self, time: Option<Duration>) -> Result<Input> {
+        let seek_args = match time {
+            Some(time) => vec!["-ss".to_string(), format!("{:.3}", time.as_secs_f64())],
+            None => vec![],
+        };
+
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg.args(&seek_args);
+
+        let mut children = vec![];
+        let mut body = None;
+
+        match &self.source {
+            FilteredSource::Ytdl(url) => {
+                let mut youtube_dl = Command::new(YOUTUBE_DL_COMMAND)
+                    .args([
+                        "-f",
+                        "webm[abr>0]/bestaudio/best",
+                        "-R",
+                        "infinite",
+                        "--no-playlist",
+                        "--ignore-config",
+                        "--no-warnings",
+                        url,
+                        "-o",
+                        "-",
+                    ])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+
+                let youtube_dl_stdout = youtube_dl.stdout.take().ok_or(Error::Stdout)?;
+
+                ffmpeg.args(["-i", "-"]).stdin(youtube_dl_stdout);
+                children.push(youtube_dl);
+            }
+            FilteredSource::Ffmpeg(input) => {
+                ffmpeg.arg("-i").arg(input).stdin(Stdio::null());
+            }
+            FilteredSource::Url(url) => {
+                let response = probe::open(url).await.map_err(|err| {
+                    Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, err))
+                })?;
+
+                ffmpeg
+                    .args(["-protocol_whitelist", PIPE_PROTOCOLS, "-i", "pipe:0"])
+                    .stdin(Stdio::piped());
+                body = Some(response);
+            }
+        }
+
+        if !self.filters.is_empty() {
+            ffmpeg.args(["-af", &self.filters]);
+        }
+
+        let mut ffmpeg = ffmpeg
+            .args([
+                "-f",
+                "s16le",
+                "-ac",
+                "2",
+                "-ar",
+                "48000",
+                "-acodec",
+                "pcm_f32le",
+                "-",
+            ])
+            .stderr(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        if let Some(response) = body {
+            let stdin = ffmpeg.stdin.take().ok_or_else(|| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "creating stdin failed",
+                ))
+            })?;
+            probe::pipe_to(response, tokio::process::ChildStdin::from_std(stdin)?);
+        }
+        children.push(ffmpeg);
+
+        Ok(Input::new(
+            true,
+            children_to_reader::<f32>(children),
+            Codec::FloatPcm,
+            Container::Raw,
+            None,
+        ))
+    }
+
+    async fn lazy_init(&mut self) -> Result<(Option<Metadata>, Codec, Container)> {
+        // ffmpeg always outputs stereo, see "-ac" above
+        let metadata = Metadata {
+            channels: Some(2),
+            ..Default::default()
+        };
+
+        Ok((Some(metadata), Codec::FloatPcm, Container::Raw))
+    }
+}
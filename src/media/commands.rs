@@ -1,10 +1,19 @@
-use poise::{command, serenity_prelude::CreateEmbed};
+use poise::{
+    command,
+    serenity_prelude::{
+        Attachment, ButtonStyle, CollectComponentInteraction, CreateComponents, CreateEmbed,
+        InteractionResponseType,
+    },
+};
 use serenity::model::prelude::GuildId;
 use tracing::{error, warn};
 
 use crate::{
-    controls::join_channel,
-    database::plugin::get_db_plugin,
+    controls::{
+        in_bot_channel, join_channel, leave_channel, listener_count,
+        permissions::{can_skip, dj_only, dj_or_requester, is_dj, owner_or_admin},
+    },
+    database::plugin::{get_db_plugin, DatabasePlugin},
     media,
     utils::{
         self, config,
@@ -15,8 +24,18 @@ use crate::{
     CommandResult, Context,
 };
 
-use super::{global_media_player::GlobalMediaPlayer, media_info::MediaInfo};
-use super::{metadata, plugin::get_media_player};
+use super::{
+    audio_cache,
+    audio_source::AudioFilter,
+    global_media_player::{GlobalMediaPlayer, LoopMode, NowPlaying, SkipVote},
+    library,
+    media_info::{MediaInfo, Requester},
+    probe::{self, UrlProbe},
+};
+use super::{
+    metadata::{self, MetadataProvider, PlaylistItems, SearchBackend},
+    plugin::{get_media_player, get_metadata_cache, get_metadata_provider},
+};
 
 // Write commands
 
@@ -30,13 +49,32 @@ use super::{metadata, plugin::get_media_player};
 )]
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "Query or url"]
+    #[description = "Audio file to play instead of a query"] file: Option<Attachment>,
+    #[description = "Query or url. Playlist urls may be followed by items, e.g. 1-20 or last 10"]
     #[rest]
-    song: String,
+    song: Option<String>,
 ) -> CommandResult {
+    let song = match file {
+        Some(file) if !is_audio_attachment(&file) => {
+            ctx.error("Only audio files can be played!").await;
+            return Ok(());
+        }
+        Some(file) => file.url,
+        None => song.unwrap_or_default(),
+    };
+
     media::commands::play_command(ctx, &song, true, false).await
 }
 
+fn is_audio_attachment(file: &Attachment) -> bool {
+    match &file.content_type {
+        Some(content_type) => {
+            content_type.starts_with("audio/") || content_type.starts_with("video/")
+        }
+        None => probe::has_audio_extension(&file.filename),
+    }
+}
+
 /// Queue a single song, ignoring playlists
 #[command(
     slash_command,
@@ -70,6 +108,230 @@ pub async fn play_next(
     media::commands::play_command(ctx, &song.join(" "), false, true).await
 }
 
+/// Search for a song and pick which result to queue
+#[command(
+    slash_command,
+    prefix_command,
+    aliases("find"),
+    broadcast_typing,
+    category = "media"
+)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Query"]
+    #[rest]
+    query: String,
+) -> CommandResult {
+    ctx.defer_ephemeral()
+        .await
+        .expect("Failed to defer message");
+
+    if query.is_empty() {
+        ctx.error("You didn't send anything dumbass").await;
+
+        return Ok(());
+    }
+
+    let metadata = get_metadata_provider(ctx.discord()).await.unwrap();
+    let backends = guild_search_backends(
+        get_db_plugin(ctx.discord()).await.unwrap().as_ref(),
+        ctx.guild_id().unwrap(),
+    );
+
+    let results = match metadata::search_results(
+        metadata.as_ref(),
+        &query,
+        SEARCH_RESULTS,
+        &backends,
+    )
+    .await
+    {
+        Ok(results) if results.is_empty() => {
+            ctx.error("No results found!").await;
+            return Ok(());
+        }
+        Ok(results) => results,
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
+
+    let id = ctx.id();
+
+    ctx.send(|m| {
+        m.content("")
+            .embed(|e| {
+                e.title(format!("Results for \"{}\"", query))
+                    .description("Pick a song to queue")
+                    .color(config::colors::info())
+            })
+            .components(|c| create_search_menu(c, id, &results))
+    })
+    .await?;
+
+    let Some(mci) = CollectComponentInteraction::new(ctx.discord())
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(std::time::Duration::from_secs(120))
+        .filter(move |mci| mci.data.custom_id == format!("{}__search", id))
+        .await
+    else {
+        return Ok(());
+    };
+
+    mci.create_interaction_response(ctx.discord(), |ir| {
+        ir.kind(InteractionResponseType::DeferredUpdateMessage)
+    })
+    .await?;
+
+    let Some(choice) = mci
+        .data
+        .values
+        .first()
+        .and_then(|value| value.parse::<usize>().ok())
+        .and_then(|i| results.get(i))
+    else {
+        ctx.error("Invalid search result").await;
+        return Ok(());
+    };
+
+    if check_or_join_vc(ctx).await.is_err() {
+        mci.edit_original_interaction_response(ctx.discord(), |m| m.components(|c| c))
+            .await?;
+        return Ok(());
+    }
+
+    let media_player = get_media_player(ctx.discord()).await.unwrap();
+    let guild_id = ctx.guild_id().unwrap();
+
+    // Search results only have partial metadata, so look up the chosen song by url
+    let embed = match queue_variant(
+        guild_id,
+        &choice.url,
+        MessageContext::from(ctx),
+        Requester::from_author(ctx).await,
+        &media_player,
+        metadata.as_ref(),
+        &probe::FFProbe,
+        &backends,
+        false,
+        None,
+        false,
+    )
+    .await
+    {
+        Ok(infos) => {
+            let info = infos.into_iter().next().unwrap();
+            let db_plugin = get_db_plugin(ctx.discord()).await.unwrap();
+            let _ = db_plugin.set_history(ctx.author().id, &info);
+
+            response::song_embed(&mut CreateEmbed::default(), &info).to_owned()
+        }
+        Err(err) => CreateEmbed::default()
+            .title("Error")
+            .description(err)
+            .color(config::colors::error())
+            .to_owned(),
+    };
+
+    mci.edit_original_interaction_response(ctx.discord(), |m| m.set_embed(embed).components(|c| c))
+        .await?;
+
+    Ok(())
+}
+
+/// Number of results shown by the search command
+const SEARCH_RESULTS: usize = 5;
+
+fn create_search_menu<'a>(
+    c: &'a mut CreateComponents,
+    id: u64,
+    results: &[MediaInfo],
+) -> &'a mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_select_menu(|m| {
+            m.custom_id(format!("{}__search", id))
+                .placeholder("Choose a song")
+                .options(|o| {
+                    for (i, info) in results.iter().enumerate() {
+                        o.create_option(|opt| {
+                            opt.label(strings::limit_string_length(&info.title, 100))
+                                .description(strings::limit_string_length(
+                                    &format!(
+                                        "{} ({})",
+                                        if !info.uploader.is_empty() {
+                                            info.uploader.as_str()
+                                        } else {
+                                            "unknown"
+                                        },
+                                        strings::song_duration(info)
+                                    ),
+                                    100,
+                                ))
+                                .value(i)
+                        });
+                    }
+                    o
+                })
+        })
+    })
+}
+
+/// The guild's search backends, set with the search-backends command or else SEARCH_BACKENDS
+fn guild_search_backends(db: &dyn DatabasePlugin, guild_id: GuildId) -> Vec<SearchBackend> {
+    match db.get_search_backends(guild_id) {
+        Ok(backends) if !backends.is_empty() => backends,
+        Ok(_) => config::search::backends(guild_id),
+        Err(err) => {
+            error!("Unable to read search backends of {}: {}", guild_id, err);
+            config::search::backends(guild_id)
+        }
+    }
+}
+
+/// Set where songs are searched, in order, or leave it empty for the default
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "search-backends",
+    category = "media"
+)]
+pub async fn search_backends(
+    ctx: Context<'_>,
+    #[description = "Backends to try in order, e.g. youtube,soundcloud"]
+    #[rest]
+    backends: Option<String>,
+) -> CommandResult {
+    let guild_id = ctx.guild_id().unwrap();
+    let db = get_db_plugin(ctx.discord()).await.unwrap();
+
+    let backends = match metadata::parse_search_backends(&backends.unwrap_or_default()) {
+        Ok(backends) => backends,
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
+
+    if let Err(err) = db.set_search_backends(guild_id, &backends) {
+        ctx.error(err).await;
+        return Ok(());
+    }
+
+    let names: Vec<&str> = guild_search_backends(db.as_ref(), guild_id)
+        .iter()
+        .map(|backend| backend.name())
+        .collect();
+
+    ctx.info(format!("Searching {} now", names.join(", then ")))
+        .await;
+
+    Ok(())
+}
+
 pub async fn check_or_join_vc(ctx: Context<'_>) -> Result<(), String> {
     let guild = ctx.guild().unwrap();
 
@@ -114,9 +376,70 @@ pub async fn check_or_join_vc(ctx: Context<'_>) -> Result<(), String> {
     Ok(())
 }
 
+/// Browse or search the music library. Play a file with `play local:<path>`
+#[command(slash_command, prefix_command, aliases("lib"), category = "media")]
+pub async fn library(
+    ctx: Context<'_>,
+    #[description = "Words in the file path"]
+    #[rest]
+    query: Option<String>,
+) -> CommandResult {
+    let Some(dir) = config::library::dir() else {
+        ctx.error("The music library is disabled!").await;
+        return Ok(());
+    };
+
+    let files = match library::list(dir.clone()).await {
+        Ok(files) => files,
+        Err(err) => {
+            error!("[library] Unable to list {:?}: {}", dir, err);
+            ctx.error("Unable to read the music library").await;
+            return Ok(());
+        }
+    };
+
+    let query = query.unwrap_or_default();
+    let files = &library::search(&files, &query);
+
+    if files.is_empty() {
+        ctx.error("No songs found in the library!").await;
+        return Ok(());
+    }
+
+    let page_size = config::library::page_size(ctx.guild_id().unwrap());
+
+    responses::create_pagination(ctx, 0, |page| async move {
+        let embed = CreateEmbed::default()
+            .title("Library")
+            .description(
+                files
+                    .iter()
+                    .enumerate()
+                    .skip(page * page_size)
+                    .take(page_size)
+                    .map(|(i, file)| format!("**{})** `{}`", i + 1, file))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            )
+            .footer(|f| {
+                f.text(strings::page_display(
+                    page + 1,
+                    files.len(),
+                    page_size,
+                    "file",
+                ))
+            })
+            .color(config::colors::library())
+            .to_owned();
+
+        Ok((embed, page, utils::ceil(files.len(), page_size)))
+    })
+    .await
+}
+
 pub async fn play_command(
     ctx: Context<'_>,
-    url: &String,
+    url: &str,
     allow_playlist: bool,
     play_next: bool,
 ) -> CommandResult {
@@ -131,6 +454,7 @@ pub async fn play_command(
     let author_id = ctx.author().id;
 
     let message_ctx = MessageContext::from(ctx);
+    let requester = Requester::from_author(ctx).await;
 
     check_or_join_vc(ctx).await?;
 
@@ -141,14 +465,32 @@ pub async fn play_command(
         return Ok(());
     }
 
+    let (url, items) = if allow_playlist {
+        match metadata::parse_playlist_items(url) {
+            Ok((url, items)) => (url, items),
+            Err(err) => {
+                ctx.error(err).await;
+                return Ok(());
+            }
+        }
+    } else {
+        (url, None)
+    };
+
     let db_plugin = get_db_plugin(ctx.discord()).await.unwrap().clone();
+    let metadata = get_metadata_provider(ctx.discord()).await.unwrap();
 
     match queue_variant(
         guild.id,
-        &url,
+        url,
         message_ctx,
+        requester,
         &media_player,
+        metadata.as_ref(),
+        &probe::FFProbe,
+        &guild_search_backends(db_plugin.as_ref(), guild.id),
         allow_playlist,
+        items.as_ref(),
         play_next,
     )
     .await
@@ -160,25 +502,9 @@ pub async fn play_command(
                 // Single song
                 let info = infos.into_iter().nth(0).unwrap();
 
-                ctx.send(|m| {
-                    m.content("").embed(|e| {
-                        e.title(&info.title)
-                            .description(format!(
-                                "**{}**",
-                                if !info.uploader.is_empty() {
-                                    info.uploader.clone()
-                                } else {
-                                    "unknown".to_string()
-                                },
-                            ))
-                            .author(|a| a.name("Queued song"))
-                            .thumbnail(&info.thumbnail)
-                            .url(&info.url)
-                            .color(config::colors::play())
-                    })
-                })
-                .await
-                .expect("Failed to send message");
+                ctx.send(|m| m.content("").embed(|e| response::song_embed(e, &info)))
+                    .await
+                    .expect("Failed to send message");
 
                 let _ = db_plugin.set_history(author_id, &info);
             } else if count > 1 {
@@ -197,6 +523,7 @@ pub async fn play_command(
                     count,
                     &info.thumbnail,
                     &info.url,
+                    items.as_ref(),
                 )
                 .await;
             };
@@ -207,16 +534,30 @@ pub async fn play_command(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn queue_variant(
     guild_id: GuildId,
-    query: &String,
+    query: &str,
     message_ctx: MessageContext,
+    requester: Requester,
     media_player: &GlobalMediaPlayer,
+    metadata: &dyn MetadataProvider,
+    url_probe: &dyn UrlProbe,
+    backends: &[SearchBackend],
     allow_playlists: bool,
+    items: Option<&PlaylistItems>,
     play_next: bool,
 ) -> Result<Vec<MediaInfo>, String> {
-    if allow_playlists && metadata::is_playlist(query) {
-        let infos = match metadata::get_playlist(query) {
+    // youtube-dl would open links to the bot's machine or network
+    if strings::is_url(query) {
+        url_probe.check_host(query).await?;
+    }
+
+    if allow_playlists
+        && !probe::is_direct_url(query)
+        && metadata::is_playlist(metadata, query).await
+    {
+        let infos = match metadata.get_playlist(query, items).await {
             Ok(infos) => infos,
             Err(err) => return Err(err),
         };
@@ -226,18 +567,31 @@ async fn queue_variant(
         }
 
         media_player
-            .enqueue_batch(guild_id, infos.clone(), message_ctx)
+            .enqueue_batch(guild_id, infos.clone(), message_ctx, requester)
             .await?;
 
         Ok(infos.into_iter().collect::<Vec<MediaInfo>>())
+    } else if items.is_some() {
+        Err("Songs can only be picked from playlists!".to_string())
     } else {
-        let info = if strings::is_url(query) {
-            match metadata::get_info(query) {
+        let info = if let Some(query) = library::parse_local(query) {
+            library::get_info(query).await?
+        } else if probe::is_direct_url(query) {
+            url_probe.get_info(query).await?
+        } else if strings::is_url(query) {
+            match metadata.get_info(query).await {
                 Ok(url) => url,
-                Err(err) => return Err(err),
+                // e.g. radio streams without a file extension
+                Err(err) => {
+                    if !url_probe.is_audio_stream(query).await {
+                        return Err(err);
+                    }
+
+                    url_probe.get_info(query).await.map_err(|_| err)?
+                }
             }
         } else {
-            match metadata::get_search(query) {
+            match metadata::search(metadata, query, backends).await {
                 Ok(url) => url,
                 Err(err) => return Err(err),
             }
@@ -245,11 +599,11 @@ async fn queue_variant(
 
         if !play_next {
             media_player
-                .enqueue(guild_id, info.clone(), message_ctx)
+                .enqueue(guild_id, info.clone(), message_ctx, requester)
                 .await?;
         } else {
             media_player
-                .enqueue_next(guild_id, info.clone(), message_ctx)
+                .enqueue_next(guild_id, info.clone(), message_ctx, requester)
                 .await?;
         }
 
@@ -260,25 +614,51 @@ async fn queue_variant(
 pub mod response {
     use super::*;
 
+    pub fn song_embed<'a>(e: &'a mut CreateEmbed, info: &MediaInfo) -> &'a mut CreateEmbed {
+        e.title(&info.title)
+            .description(format!(
+                "**{}**",
+                if !info.uploader.is_empty() {
+                    info.uploader.clone()
+                } else {
+                    "unknown".to_string()
+                },
+            ))
+            .author(|a| a.name("Queued song"))
+            .thumbnail(&info.thumbnail)
+            .color(config::colors::play());
+
+        if let Some(link) = info.link() {
+            e.url(link);
+        }
+
+        e
+    }
+
     pub async fn playlist_response(
         ctx: Context<'_>,
-        title: &String,
-        uploader: &String,
+        title: &str,
+        uploader: &str,
         count: usize,
-        thumbnail: &String,
-        url: &String,
+        thumbnail: &str,
+        url: &str,
+        items: Option<&PlaylistItems>,
     ) {
         ctx.send(|m| {
             m.content("").embed(|e| {
                 e.title(&title)
                     .description(format!(
-                        "Uploader: **{}**\nTracks: **{}**",
+                        "Uploader: **{}**\nTracks: **{}**{}",
                         if !uploader.is_empty() {
-                            uploader.as_str()
+                            uploader
                         } else {
                             &"unknown"
                         },
-                        count
+                        count,
+                        match items {
+                            Some(items) => format!("\nItems: **{}**", items),
+                            None => "".to_string(),
+                        }
                     ))
                     .author(|a| a.name("Queued playlist"))
                     .thumbnail(&thumbnail)
@@ -294,98 +674,735 @@ pub mod response {
 }
 
 /// Skip the current song
-#[command(slash_command, prefix_command, broadcast_typing, category = "media")]
+#[command(
+    slash_command,
+    prefix_command,
+    broadcast_typing,
+    check = "can_skip",
+    category = "media"
+)]
 pub async fn skip(ctx: Context<'_>) -> CommandResult {
     let guild = ctx.guild().unwrap();
     let guild_id = guild.id;
 
-    let res = get_media_player(ctx.discord())
-        .await
-        .unwrap()
-        .skip(guild_id)
-        .await;
+    let media_player = get_media_player(ctx.discord()).await.unwrap();
 
-    match res {
-        Ok(_) => ctx.info("Skipped current song!").await,
-        Err(err) => ctx.error(err).await,
+    if config::skip::vote_ratio(guild_id).is_none() {
+        match media_player.skip(guild_id).await {
+            Ok(_) => ctx.info("Skipped current song!").await,
+            Err(err) => ctx.error(err).await,
+        }
+
+        return Ok(());
     }
 
-    Ok(())
-}
+    let title = match media_player.now_playing(guild_id).await {
+        Ok(Some(now_playing)) => now_playing.info.title,
+        Ok(None) => {
+            ctx.error("No songs playing!").await;
+            return Ok(());
+        }
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
 
-/// Clear the queue
-#[command(slash_command, prefix_command, broadcast_typing, category = "media")]
-pub async fn clear(ctx: Context<'_>) -> CommandResult {
-    let guild = ctx.guild().unwrap();
-    let guild_id = guild.id;
+    if !in_bot_channel(ctx.discord(), guild_id, ctx.author().id) {
+        ctx.send(|m| {
+            responses::format_error(m, "Only listeners in the voice channel can vote to skip")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
 
-    let res = get_media_player(ctx.discord())
-        .await
-        .unwrap()
-        .clear(guild_id)
+    let author_is_dj = match ctx.author_member().await {
+        Some(member) => is_dj(ctx.discord(), &member).await,
+        None => false,
+    };
+
+    let vote = media_player
+        .vote_skip(
+            guild_id,
+            ctx.author().id,
+            author_is_dj,
+            config::skip::required_votes(
+                guild_id,
+                listener_count(ctx.discord(), guild_id).unwrap_or(1),
+            ),
+            None,
+        )
         .await;
 
-    match res {
-        Ok(_) => ctx.info("Cleared the queue!").await,
-        Err(err) => ctx.error(err).await,
-    }
+    let vote = match vote {
+        Ok(vote) if vote.skipped => {
+            ctx.info("Skipped current song!").await;
+            return Ok(());
+        }
+        Ok(vote) => vote,
+        Err(err) => {
+            ctx.error(err).await;
+            return Ok(());
+        }
+    };
 
-    Ok(())
-}
+    let id = ctx.id();
+    let track_number = vote.track_number;
 
-/// Jump to a time in the current song
-#[command(slash_command, prefix_command, category = "media")]
-pub async fn seek(ctx: Context<'_>, to: String) -> CommandResult {
-    let media_player = get_media_player(ctx.discord()).await.unwrap();
+    ctx.send(|m| {
+        m.embeds.push(format_skip_vote(&title, &vote));
+        m.components(|c| create_vote_button(c, id))
+    })
+    .await?;
 
-    let guild_id = ctx.guild().unwrap().id;
+    while let Some(mci) = CollectComponentInteraction::new(ctx.discord())
+        .channel_id(ctx.channel_id())
+        .timeout(std::time::Duration::from_secs(120))
+        .filter(move |mci| mci.data.custom_id == format!("{}__vote_skip", id))
+        .await
+    {
+        if !in_bot_channel(ctx.discord(), guild_id, mci.user.id) {
+            responses::interaction_error(
+                ctx.discord(),
+                &mci,
+                "Only listeners in the voice channel can vote to skip",
+            )
+            .await;
+            continue;
+        }
 
-    let time = if let Ok(seconds) = to.parse::<i64>() {
-        seconds
-    } else if strings::is_timestamp(&to) {
-        strings::parse_timestamp(&to)
-    } else {
-        ctx.error(format!("{} isn't a valid timestamp.", to)).await;
+        let voter_is_dj = match &mci.member {
+            Some(member) => is_dj(ctx.discord(), member).await,
+            None => false,
+        };
 
-        return Ok(());
-    };
+        let vote = media_player
+            .vote_skip(
+                guild_id,
+                mci.user.id,
+                voter_is_dj,
+                config::skip::required_votes(
+                    guild_id,
+                    listener_count(ctx.discord(), guild_id).unwrap_or(1),
+                ),
+                Some(track_number),
+            )
+            .await;
 
-    if time < 0 {
-        ctx.error("Cannot seek to negative time.").await;
+        let (embed, done) = match vote {
+            Ok(vote) => (format_skip_vote(&title, &vote), vote.skipped),
+            Err(err) => (
+                CreateEmbed::default()
+                    .title("Vote skip")
+                    .description(err)
+                    .color(config::colors::error())
+                    .to_owned(),
+                true,
+            ),
+        };
 
-        return Ok(());
-    }
+        let mut msg = mci.message.clone();
+        msg.edit(ctx.discord(), |m| {
+            m.set_embed(embed)
+                .components(|c| if done { c } else { create_vote_button(c, id) })
+        })
+        .await?;
 
-    match media_player.seek(guild_id, time).await {
-        Ok(_) => {
-            ctx.info(format!("Seeking to {}", strings::format_timestamp(time)))
-                .await;
-        }
-        Err(err) => {
-            ctx.error(&err).await;
-            warn!("Seek error: {}", &err);
+        mci.create_interaction_response(ctx.discord(), |ir| {
+            ir.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+        if done {
+            break;
         }
     }
 
     Ok(())
 }
 
-// Read commands
+fn format_skip_vote(title: &str, vote: &SkipVote) -> CreateEmbed {
+    CreateEmbed::default()
+        .title("Vote skip")
+        .description(if vote.skipped {
+            format!("Skipped **{}**", strings::escape_string(title))
+        } else {
+            format!(
+                "Skip **{}**? {}/{} votes",
+                strings::escape_string(title),
+                vote.votes,
+                vote.required
+            )
+        })
+        .color(config::colors::info())
+        .to_owned()
+}
 
-/// Show the song queue
-#[command(slash_command, prefix_command, category = "media")]
-pub async fn queue(
-    ctx: Context<'_>,
-    #[description = "Page #"]
-    #[min = 1]
-    page: Option<i64>,
-) -> CommandResult {
-    let initial_page = validate_page(ctx, page).await?;
+fn create_vote_button(c: &mut CreateComponents, id: u64) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.label("Vote to skip")
+                .custom_id(format!("{}__vote_skip", id))
+                .style(ButtonStyle::Primary)
+        })
+    })
+}
 
-    let guild = ctx.guild().unwrap();
-    let guild_id = guild.id;
+/// Pause the current song
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_or_requester",
+    category = "media"
+)]
+pub async fn pause(ctx: Context<'_>) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
 
-    let queue_page_size = config::queue::page_size(guild_id);
+    let media_player = get_media_player(ctx.discord()).await.unwrap();
+
+    match media_player.pause(guild_id).await {
+        Ok(paused_at) => {
+            ctx.info("Paused").await;
+
+            if let Some(timeout) = config::timeouts::pause(guild_id) {
+                let manager = songbird::get(ctx.discord())
+                    .await
+                    .expect("Songbird Voice client placed in at initialisation.");
+                let message_ctx = MessageContext::from(ctx);
+
+                tokio::spawn(async move {
+                    tokio::time::sleep(timeout).await;
+
+                    // Only leave if the song hasn't been resumed or paused again since
+                    if media_player.paused_at(guild_id).await != Ok(Some(paused_at)) {
+                        return;
+                    }
+
+                    match leave_channel(&manager, &media_player, guild_id).await {
+                        Ok(_) => {
+                            message_ctx
+                                .send_info("Left voice channel after being paused for too long")
+                                .await
+                        }
+                        Err(err) => warn!("Unable to leave paused channel: {}", err),
+                    }
+                });
+            }
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Resume the current song
+#[command(
+    slash_command,
+    prefix_command,
+    aliases("unpause"),
+    check = "dj_or_requester",
+    category = "media"
+)]
+pub async fn resume(ctx: Context<'_>) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .resume(guild_id)
+        .await;
+
+    match res {
+        Ok(_) => ctx.info("Resumed").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Set or show the volume
+#[command(
+    slash_command,
+    prefix_command,
+    aliases("vol"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent. Shows the current volume if empty"]
+    #[min = 0]
+    #[max = 200]
+    percent: Option<u32>,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let media_player = get_media_player(ctx.discord()).await.unwrap();
+
+    let Some(percent) = percent else {
+        match media_player.volume(guild_id).await {
+            Ok(volume) => {
+                ctx.info(format!("Volume is at {}%", (volume * 100.0).round()))
+                    .await
+            }
+            Err(err) => ctx.error(err).await,
+        }
+
+        return Ok(());
+    };
+
+    if percent > 200 {
+        ctx.error("Volume cannot be more than 200%").await;
+
+        return Ok(());
+    }
+
+    match media_player
+        .set_volume(guild_id, percent as f32 / 100.0)
+        .await
+    {
+        Ok(_) => ctx.info(format!("Volume set to {}%", percent)).await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Toggle loudness normalization for later songs
+#[command(slash_command, prefix_command, check = "dj_only", category = "media")]
+pub async fn normalize(
+    ctx: Context<'_>,
+    #[description = "Turn normalization on or off. Toggles if empty"] enabled: Option<bool>,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .set_normalize(guild_id, enabled)
+        .await;
+
+    match res {
+        Ok(true) => {
+            ctx.info("Normalization on. Applies from the next song.")
+                .await
+        }
+        Ok(false) => {
+            ctx.info("Normalization off. Applies from the next song.")
+                .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Toggle an audio effect. Clears all effects if empty
+#[command(slash_command, prefix_command, check = "dj_only", category = "media")]
+pub async fn filter(
+    ctx: Context<'_>,
+    #[description = "Effect to toggle"] preset: Option<AudioFilter>,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .toggle_filter(guild_id, preset)
+        .await;
+
+    match res {
+        Ok(filters) if filters.is_empty() => ctx.info("Cleared all filters").await,
+        Ok(filters) => {
+            ctx.info(format!("Active filters: {}", format_filters(&filters)))
+                .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+fn format_filters(filters: &[AudioFilter]) -> String {
+    if filters.is_empty() {
+        "None".to_string()
+    } else {
+        filters
+            .iter()
+            .map(|filter| format!("`{}`", filter))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// Queue the last played song to play next
+#[command(
+    slash_command,
+    prefix_command,
+    aliases("prev", "back"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn previous(ctx: Context<'_>) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .previous(guild_id)
+        .await;
+
+    match res {
+        Ok(info) => {
+            ctx.info(format!(
+                "Playing **{}** next",
+                strings::escape_string(&info.title)
+            ))
+            .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Restart the current song
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_or_requester",
+    category = "media"
+)]
+pub async fn replay(ctx: Context<'_>) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .replay(guild_id)
+        .await;
+
+    match res {
+        Ok(_) => ctx.info("Replaying current song").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Clear the queue
+#[command(
+    slash_command,
+    prefix_command,
+    broadcast_typing,
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn clear(ctx: Context<'_>) -> CommandResult {
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .clear(guild_id)
+        .await;
+
+    match res {
+        Ok(_) => ctx.info("Cleared the queue!").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Remove a song from the queue
+#[command(
+    slash_command,
+    prefix_command,
+    aliases("rm"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Position in the queue"]
+    #[min = 2]
+    position: usize,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .remove(guild_id, position)
+        .await;
+
+    match res {
+        Ok(info) => {
+            ctx.info(format!(
+                "Removed **{}**",
+                strings::escape_string(&info.title)
+            ))
+            .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Move a song to a different position in the queue
+#[command(
+    slash_command,
+    prefix_command,
+    rename = "move",
+    aliases("mv"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn move_item(
+    ctx: Context<'_>,
+    #[description = "Position of the song to move"]
+    #[min = 2]
+    from: usize,
+    #[description = "Position to move the song to"]
+    #[min = 2]
+    to: usize,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .move_item(guild_id, from, to)
+        .await;
+
+    match res {
+        Ok(info) => {
+            ctx.info(format!(
+                "Moved **{}** to position {}",
+                strings::escape_string(&info.title),
+                to
+            ))
+            .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Swap two songs in the queue
+#[command(slash_command, prefix_command, check = "dj_only", category = "media")]
+pub async fn swap(
+    ctx: Context<'_>,
+    #[description = "Position of the first song"]
+    #[min = 2]
+    a: usize,
+    #[description = "Position of the second song"]
+    #[min = 2]
+    b: usize,
+) -> CommandResult {
+    let guild_id = ctx.guild().unwrap().id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .swap(guild_id, a, b)
+        .await;
+
+    match res {
+        Ok((a_info, b_info)) => {
+            ctx.info(format!(
+                "Swapped **{}** and **{}**",
+                strings::escape_string(&b_info.title),
+                strings::escape_string(&a_info.title)
+            ))
+            .await
+        }
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Shuffle the queue
+#[command(
+    slash_command,
+    prefix_command,
+    broadcast_typing,
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn shuffle(ctx: Context<'_>) -> CommandResult {
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .shuffle(guild_id)
+        .await;
+
+    match res {
+        Ok(_) => ctx.info("Shuffled the queue!").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Toggle inserting newly queued songs at random positions
+#[command(
+    slash_command,
+    prefix_command,
+    rename = "shuffle-mode",
+    aliases("sm"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn shuffle_mode(
+    ctx: Context<'_>,
+    #[description = "Turn shuffle mode on or off. Toggles if empty"] enabled: Option<bool>,
+) -> CommandResult {
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .set_shuffle_mode(guild_id, enabled)
+        .await;
+
+    match res {
+        Ok(true) => ctx.info("Shuffle mode on").await,
+        Ok(false) => ctx.info("Shuffle mode off").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Toggle taking turns between the people who queued songs
+#[command(
+    slash_command,
+    prefix_command,
+    rename = "fair-mode",
+    aliases("fair"),
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn fair_mode(
+    ctx: Context<'_>,
+    #[description = "Turn fair mode on or off. Toggles if empty"] enabled: Option<bool>,
+) -> CommandResult {
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .set_fair_mode(guild_id, enabled)
+        .await;
+
+    match res {
+        Ok(true) => ctx.info("Fair mode on, requesters take turns").await,
+        Ok(false) => ctx.info("Fair mode off").await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Loop the current song or the whole queue
+#[command(
+    slash_command,
+    prefix_command,
+    rename = "loop",
+    check = "dj_only",
+    category = "media"
+)]
+pub async fn loop_mode(
+    ctx: Context<'_>,
+    #[description = "off, track or queue"] mode: LoopMode,
+) -> CommandResult {
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let res = get_media_player(ctx.discord())
+        .await
+        .unwrap()
+        .set_loop_mode(guild_id, mode)
+        .await;
+
+    match res {
+        Ok(_) => ctx.info(format!("Loop mode set to **{}**", mode)).await,
+        Err(err) => ctx.error(err).await,
+    }
+
+    Ok(())
+}
+
+/// Jump to a time in the current song
+#[command(
+    slash_command,
+    prefix_command,
+    check = "dj_or_requester",
+    category = "media"
+)]
+pub async fn seek(ctx: Context<'_>, to: String) -> CommandResult {
+    let media_player = get_media_player(ctx.discord()).await.unwrap();
+
+    let guild_id = ctx.guild().unwrap().id;
+
+    let time = if let Ok(seconds) = to.parse::<i64>() {
+        seconds
+    } else if strings::is_timestamp(&to) {
+        strings::parse_timestamp(&to)
+    } else {
+        ctx.error(format!("{} isn't a valid timestamp.", to)).await;
+
+        return Ok(());
+    };
+
+    if time < 0 {
+        ctx.error("Cannot seek to negative time.").await;
+
+        return Ok(());
+    }
+
+    match media_player.seek(guild_id, time).await {
+        Ok(_) => {
+            ctx.info(format!("Seeking to {}", strings::format_timestamp(time)))
+                .await;
+        }
+        Err(err) => {
+            ctx.error(&err).await;
+            warn!("Seek error: {}", &err);
+        }
+    }
+
+    Ok(())
+}
+
+// Read commands
+
+/// Show the song queue
+#[command(slash_command, prefix_command, category = "media")]
+pub async fn queue(
+    ctx: Context<'_>,
+    #[description = "Page #"]
+    #[min = 1]
+    page: Option<i64>,
+) -> CommandResult {
+    let initial_page = validate_page(ctx, page).await?;
+
+    let guild = ctx.guild().unwrap();
+    let guild_id = guild.id;
+
+    let queue_page_size = config::queue::page_size(guild_id);
 
     responses::create_pagination(ctx, initial_page, |next_page| async move {
         let media_player = get_media_player(ctx.discord()).await.unwrap();
@@ -394,13 +1411,19 @@ pub async fn queue(
             .read_queue(guild_id, next_page * queue_page_size, queue_page_size)
             .await;
 
+        let loop_mode = media_player.loop_mode(guild_id).await.unwrap_or_default();
+        let (duration, live) = media_player
+            .queue_duration(guild_id)
+            .await
+            .unwrap_or_default();
+
         match res {
             Ok((queue, len)) => {
                 if len == 0 {
                     Err("The queue is empty".to_string())
                 } else {
                     Ok((
-                        responses::format_embed_playlist(
+                        responses::format_embed_queue(
                             &mut CreateEmbed::default(),
                             queue.iter(),
                             len,
@@ -408,6 +1431,19 @@ pub async fn queue(
                             next_page,
                         )
                         .title("Queue")
+                        .field("Loop", loop_mode, true)
+                        .field(
+                            "Duration",
+                            match live {
+                                0 => strings::format_timestamp(duration),
+                                live => format!(
+                                    "{} + {} live",
+                                    strings::format_timestamp(duration),
+                                    live
+                                ),
+                            },
+                            true,
+                        )
                         .color(config::colors::queue())
                         .to_owned(),
                         next_page,
@@ -423,6 +1459,56 @@ pub async fn queue(
     Ok(())
 }
 
+/// Show the songs played in this session
+#[command(slash_command, prefix_command, aliases("played"), category = "media")]
+pub async fn recent(
+    ctx: Context<'_>,
+    #[description = "Page #"]
+    #[min = 1]
+    page: Option<i64>,
+) -> CommandResult {
+    let initial_page = validate_page(ctx, page).await?;
+
+    let guild_id = ctx.guild().unwrap().id;
+
+    let page_size = config::queue::page_size(guild_id);
+
+    responses::create_pagination(ctx, initial_page, |next_page| async move {
+        let media_player = get_media_player(ctx.discord()).await.unwrap();
+
+        let res = media_player
+            .read_played(guild_id, next_page * page_size, page_size)
+            .await;
+
+        match res {
+            Ok((played, len)) => {
+                if len == 0 {
+                    Err("No songs have been played yet".to_string())
+                } else {
+                    Ok((
+                        responses::format_embed_playlist(
+                            &mut CreateEmbed::default(),
+                            played.iter(),
+                            len,
+                            guild_id,
+                            next_page,
+                        )
+                        .title("Recently played in this session")
+                        .color(config::colors::history())
+                        .to_owned(),
+                        next_page,
+                        utils::ceil(len, page_size),
+                    ))
+                }
+            }
+            Err(err) => Err(err),
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
 /// Get the currently playing song
 #[command(
     slash_command,
@@ -439,26 +1525,58 @@ pub async fn now_playing(ctx: Context<'_>) -> CommandResult {
 
     let res = media_player.now_playing(guild_id).await;
 
+    let loop_mode = media_player.loop_mode(guild_id).await.unwrap_or_default();
+
     match res {
         Ok(res_tuple) => {
             match res_tuple {
-                Some((info, time)) => {
+                Some(NowPlaying {
+                    info,
+                    requester,
+                    position: time,
+                    paused,
+                    filters,
+                }) => {
                     ctx.send(|m| {
                         m.content("").embed(|e| {
                             e.title(&info.title)
-                                .description(format!(
-                                    "`{} ({}/{})`",
-                                    strings::create_progress_bar(
-                                        guild_id,
-                                        time as f32 / info.duration as f32,
-                                    ),
-                                    strings::format_timestamp(time),
-                                    strings::format_timestamp(info.duration)
-                                ))
-                                .author(|a| a.name("Now playing:"))
-                                .url(&info.url)
-                                .thumbnail(info.thumbnail)
-                                .color(config::colors::now_playing())
+                                .description(if info.live {
+                                    format!(
+                                        "{}🔴 **LIVE** `{}`",
+                                        if paused { "⏸ " } else { "" },
+                                        strings::format_timestamp(time)
+                                    )
+                                } else if info.duration <= 0 {
+                                    // unknown length, so there's no progress to show
+                                    format!(
+                                        "{}`{}`",
+                                        if paused { "⏸ " } else { "" },
+                                        strings::format_timestamp(time)
+                                    )
+                                } else {
+                                    format!(
+                                        "{}`{} ({}/{})`",
+                                        if paused { "⏸ " } else { "" },
+                                        strings::create_progress_bar(
+                                            guild_id,
+                                            time as f32 / info.duration as f32,
+                                        ),
+                                        strings::format_timestamp(time),
+                                        strings::format_timestamp(info.duration)
+                                    )
+                                })
+                                .author(|a| a.name(if paused { "Paused:" } else { "Now playing:" }))
+                                .field("Requested by", &requester.name, true)
+                                .field("Loop", loop_mode, true)
+                                .field("Filters", format_filters(&filters), true)
+                                .thumbnail(&info.thumbnail)
+                                .color(config::colors::now_playing());
+
+                            if let Some(link) = info.link() {
+                                e.url(link);
+                            }
+
+                            e
                         })
                     })
                     .await
@@ -485,8 +1603,9 @@ pub async fn timestamp(ctx: Context<'_>) -> CommandResult {
 
     match np {
         Ok(result) => {
-            if let Some((song, _)) = result {
-                let timestamps = strings::parse_description_timestamps(song.description);
+            if let Some(now_playing) = result {
+                let timestamps =
+                    strings::parse_description_timestamps(now_playing.info.description);
 
                 ctx.info(format!(
                     "{}",
@@ -506,3 +1625,236 @@ pub async fn timestamp(ctx: Context<'_>) -> CommandResult {
 
     Ok(())
 }
+
+/// Show the metadata cache hit rate. Use `cache purge` to empty the cache
+#[command(prefix_command, check = "owner_or_admin", category = "debug")]
+pub async fn cache(ctx: Context<'_>, action: Option<String>) -> CommandResult {
+    let cache = get_metadata_cache(ctx.discord()).await.unwrap();
+
+    match action.as_deref() {
+        None => {
+            let stats = cache.stats();
+
+            ctx.info(format!(
+                "Hit rate: **{:.1}%** ({} hits, {} misses)\nStorage: **{}**\nTTL: **{}**",
+                stats.hit_rate() * 100.0,
+                stats.hits,
+                stats.misses,
+                if stats.in_memory {
+                    "memory"
+                } else {
+                    "database"
+                },
+                match config::cache::metadata_ttl() {
+                    Some(ttl) => format!("{} hours", ttl.as_secs() / 60 / 60),
+                    None => "disabled".to_string(),
+                }
+            ))
+            .await;
+        }
+        Some("purge") => match cache.purge() {
+            Ok(count) => ctx.info(format!("Purged {} cached lookups", count)).await,
+            Err(err) => ctx.error(err).await,
+        },
+        Some(action) => ctx.error(format!("Unknown action: {}", action)).await,
+    }
+
+    Ok(())
+}
+
+/// Show how much of the audio cache is used
+#[command(
+    prefix_command,
+    rename = "audio-cache",
+    check = "owner_or_admin",
+    category = "debug"
+)]
+pub async fn audio_cache(ctx: Context<'_>) -> CommandResult {
+    match audio_cache::usage() {
+        Some(Ok(usage)) => {
+            let cap = config::cache::audio_size();
+
+            ctx.info(format!(
+                "Files: **{}**\nUsed: **{} / {} MB** ({:.1}%)",
+                usage.files,
+                usage.bytes / 1024 / 1024,
+                cap / 1024 / 1024,
+                usage.bytes as f64 / cap.max(1) as f64 * 100.0
+            ))
+            .await;
+        }
+        Some(Err(err)) => ctx.error(err).await,
+        None => ctx.error("The audio cache is disabled!").await,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::fake_metadata::{
+        mock_media_player, mock_message_ctx, mock_requester, FakeMetadataProvider, FakeUrlProbe,
+        GUILD_ID, MIXCLOUD_PLAYLIST_URL, PLAYLIST_URL, RADIO_URL, SEARCH_QUERY, VIDEO_URL,
+    };
+    use crate::media::media_info::MediaSource;
+
+    async fn queue(
+        media_player: &GlobalMediaPlayer,
+        query: &str,
+        allow_playlists: bool,
+        play_next: bool,
+    ) -> Result<Vec<MediaInfo>, String> {
+        let (query, items) = metadata::parse_playlist_items(query)?;
+
+        queue_variant(
+            GUILD_ID,
+            query,
+            mock_message_ctx(),
+            mock_requester(),
+            media_player,
+            &FakeMetadataProvider::new(),
+            &FakeUrlProbe::new(),
+            &[SearchBackend::YouTube],
+            allow_playlists,
+            items.as_ref(),
+            play_next,
+        )
+        .await
+    }
+
+    /// Urls of the queued songs, without the now playing slot
+    async fn queued_urls(media_player: &GlobalMediaPlayer) -> Vec<String> {
+        let (queue, _) = media_player.read_queue(GUILD_ID, 1, 10).await.unwrap();
+        queue.into_iter().map(|(info, _)| info.url).collect()
+    }
+
+    #[tokio::test]
+    async fn queue_url() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, VIDEO_URL, true, false).await.unwrap();
+
+        assert_eq!(infos.len(), 1);
+        assert_eq!(queued_urls(&media_player).await, vec![VIDEO_URL]);
+    }
+
+    #[tokio::test]
+    async fn queue_search() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, SEARCH_QUERY, true, false)
+            .await
+            .unwrap();
+
+        assert_eq!(queued_urls(&media_player).await, vec![infos[0].url.clone()]);
+    }
+
+    #[tokio::test]
+    async fn queue_playlist() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, PLAYLIST_URL, true, false)
+            .await
+            .unwrap();
+
+        assert_eq!(infos.len(), 3);
+        assert_eq!(
+            queued_urls(&media_player).await,
+            infos.into_iter().map(|info| info.url).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn queue_playlist_other_host() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, MIXCLOUD_PLAYLIST_URL, true, false)
+            .await
+            .unwrap();
+
+        assert_eq!(infos.len(), 3);
+        assert_eq!(queued_urls(&media_player).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn queue_playlist_items() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, &format!("{} 1,3", PLAYLIST_URL), true, false)
+            .await
+            .unwrap();
+
+        assert_eq!(infos.len(), 2);
+        assert_eq!(
+            queued_urls(&media_player).await,
+            infos.into_iter().map(|info| info.url).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn queue_items_of_song() {
+        let media_player = mock_media_player().await;
+
+        let result = queue(&media_player, &format!("{} 1-2", VIDEO_URL), true, false).await;
+
+        assert!(result.is_err());
+        assert!(queued_urls(&media_player).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queue_playlist_not_allowed() {
+        let media_player = mock_media_player().await;
+
+        let result = queue(&media_player, PLAYLIST_URL, false, false).await;
+
+        assert!(result.is_err());
+        assert!(queued_urls(&media_player).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queue_next() {
+        let media_player = mock_media_player().await;
+
+        queue(&media_player, SEARCH_QUERY, true, false)
+            .await
+            .unwrap();
+        queue(&media_player, VIDEO_URL, true, true).await.unwrap();
+
+        assert_eq!(queued_urls(&media_player).await[0], VIDEO_URL);
+    }
+
+    #[tokio::test]
+    async fn queue_unknown_url() {
+        let media_player = mock_media_player().await;
+
+        let result = queue(&media_player, "https://example.com/missing", true, false).await;
+
+        assert!(result.is_err());
+        assert!(queued_urls(&media_player).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queue_internal_url() {
+        let media_player = mock_media_player().await;
+
+        let result = queue(&media_player, "http://127.0.0.1:8080/video", true, false).await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            "Links to private networks can't be played"
+        );
+        assert!(queued_urls(&media_player).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queue_radio_stream() {
+        let media_player = mock_media_player().await;
+
+        let infos = queue(&media_player, RADIO_URL, true, false).await.unwrap();
+
+        assert_eq!(infos[0].source, MediaSource::Direct);
+        assert!(infos[0].live);
+        assert_eq!(queued_urls(&media_player).await, vec![RADIO_URL]);
+    }
+}
@@ -0,0 +1,304 @@
+use poise::{
+    async_trait,
+    serenity_prelude::{ChannelId, GuildId, Http, UserId},
+};
+use std::{
+    collections::{HashMap, LinkedList},
+    ops::Deref,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use url::Url;
+
+use crate::{
+    database::{plugin::DatabasePlugin, sqlite_plugin::SQLitePlugin},
+    utils::message_context::MessageContext,
+};
+
+use super::{
+    global_media_player::GlobalMediaPlayer,
+    media_info::{MediaInfo, MediaSource, Requester},
+    metadata::{
+        parse_info, parse_is_playlist, parse_playlist, parse_search, MetadataProvider,
+        PlaylistItems, SearchBackend,
+    },
+    probe::{check_host, parse_probe, UrlProbe},
+};
+
+pub const VIDEO_URL: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+pub const LIVE_URL: &str = "https://www.youtube.com/watch?v=jfKfPfyJRdk";
+pub const SEARCH_QUERY: &str = "hello";
+/// Query that only SoundCloud has results for
+pub const SOUNDCLOUD_QUERY: &str = "ambient";
+pub const PLAYLIST_URL: &str =
+    "https://www.youtube.com/playlist?list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS";
+pub const PLAYLIST_VIDEO_URL: &str =
+    "https://www.youtube.com/watch?v=djV11Xbc914&list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS";
+/// Playlist on a host that is only recognized by youtube-dl
+pub const MIXCLOUD_PLAYLIST_URL: &str = "https://www.mixcloud.com/NTSRadio/playlists/ambient/";
+/// First entry of `MIXCLOUD_PLAYLIST_URL`, which youtube-dl returns as a single format
+pub const MIXCLOUD_URL: &str =
+    "https://www.mixcloud.com/NTSRadio/floating-points-8th-october-2019/";
+/// Second entry of `MIXCLOUD_PLAYLIST_URL`, which is flat until it's looked up
+pub const MIXCLOUD_ENTRY_URL: &str =
+    "https://www.mixcloud.com/NTSRadio/the-ambient-show-with-hiroshi-yoshimura-special/";
+/// Radio stream that only ffprobe can read
+pub const RADIO_URL: &str = "https://ice1.somafm.com/groovesalad-128-mp3";
+
+const VIDEO_JSON: &str = include_str!("fixtures/video.json");
+const VIDEO_FLAT_JSON: &str = include_str!("fixtures/video_flat.json");
+const LIVE_JSON: &str = include_str!("fixtures/live.json");
+const SEARCH_JSON: &str = include_str!("fixtures/search.json");
+const SEARCH_RESULTS_JSON: &str = include_str!("fixtures/search_results.json");
+const SOUNDCLOUD_SEARCH_JSON: &str = include_str!("fixtures/soundcloud_search.json");
+const PLAYLIST_JSON: &str = include_str!("fixtures/playlist.json");
+const PLAYLIST_FLAT_JSON: &str = include_str!("fixtures/playlist_flat.json");
+const MIXCLOUD_TYPE_JSON: &str = include_str!("fixtures/mixcloud_type.json");
+const MIXCLOUD_JSON: &str = include_str!("fixtures/mixcloud.json");
+const MIXCLOUD_FLAT_JSON: &str = include_str!("fixtures/mixcloud_flat.json");
+const MIXCLOUD_TRACK_JSON: &str = include_str!("fixtures/mixcloud_track.json");
+const MIXCLOUD_ENTRY_JSON: &str = include_str!("fixtures/mixcloud_entry.json");
+const FFPROBE_RADIO_JSON: &str = include_str!("fixtures/ffprobe_radio.json");
+
+/// Guild of the players created by `mock_media_player`
+pub const GUILD_ID: GuildId = GuildId(1);
+
+pub fn mock_message_ctx() -> MessageContext {
+    MessageContext {
+        channel: ChannelId(1),
+        http: Arc::new(Http::new("")),
+    }
+}
+
+pub fn mock_requester() -> Requester {
+    Requester {
+        user_id: UserId(1),
+        name: "user".to_string(),
+    }
+}
+
+/// A player for `GUILD_ID` that isn't connected to a voice channel
+pub async fn mock_media_player() -> GlobalMediaPlayer {
+    let media_player = GlobalMediaPlayer::UNINITIALIZED;
+    media_player.init_self().await;
+    media_player
+        .start_without_voice(GUILD_ID, mock_message_ctx())
+        .await
+        .unwrap();
+    media_player
+}
+
+/// An empty database of its own, so that tests can run at the same time. The file is removed
+/// when it is dropped, even if the test panics.
+pub struct MockDb {
+    db: SQLitePlugin,
+    path: PathBuf,
+}
+
+impl Deref for MockDb {
+    type Target = SQLitePlugin;
+
+    fn deref(&self) -> &SQLitePlugin {
+        &self.db
+    }
+}
+
+impl Drop for MockDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub fn mock_db(name: &str) -> MockDb {
+    let path = std::env::temp_dir().join(format!("mimicri-{}-{}.sqlite", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let db = SQLitePlugin {
+        path: path.to_string_lossy().to_string(),
+    };
+    db.init_db();
+
+    MockDb { db, path }
+}
+
+/// Replays recorded youtube-dl output instead of running youtube-dl.
+///
+/// Unknown urls replay empty output, like youtube-dl does for urls it can't handle.
+#[derive(Default)]
+pub struct FakeMetadataProvider {
+    infos: HashMap<String, &'static str>,
+    searches: HashMap<String, &'static str>,
+    types: HashMap<String, &'static str>,
+    playlists: HashMap<String, (&'static str, &'static str)>,
+}
+
+impl FakeMetadataProvider {
+    /// Creates a provider that knows the songs and playlists in `src/media/fixtures`
+    pub fn new() -> Self {
+        FakeMetadataProvider::default()
+            .with_info(VIDEO_URL, VIDEO_JSON)
+            .with_info(LIVE_URL, LIVE_JSON)
+            .with_info(MIXCLOUD_URL, MIXCLOUD_TRACK_JSON)
+            .with_info(MIXCLOUD_ENTRY_URL, MIXCLOUD_ENTRY_JSON)
+            .with_search(
+                &SearchBackend::YouTube.search_term(SEARCH_QUERY, 1),
+                SEARCH_JSON,
+            )
+            .with_search(
+                &SearchBackend::YouTube.search_term(SEARCH_QUERY, 5),
+                SEARCH_RESULTS_JSON,
+            )
+            .with_search(
+                &SearchBackend::SoundCloud.search_term(SOUNDCLOUD_QUERY, 1),
+                SOUNDCLOUD_SEARCH_JSON,
+            )
+            .with_playlist(VIDEO_URL, VIDEO_JSON, VIDEO_FLAT_JSON)
+            .with_playlist(PLAYLIST_URL, PLAYLIST_JSON, PLAYLIST_FLAT_JSON)
+            .with_playlist(PLAYLIST_VIDEO_URL, PLAYLIST_JSON, PLAYLIST_FLAT_JSON)
+            .with_type(MIXCLOUD_PLAYLIST_URL, MIXCLOUD_TYPE_JSON)
+            .with_playlist(MIXCLOUD_PLAYLIST_URL, MIXCLOUD_JSON, MIXCLOUD_FLAT_JSON)
+    }
+
+    /// Replays `output` for `youtube-dl -j --no-playlist <url>`
+    pub fn with_info(mut self, url: &str, output: &'static str) -> Self {
+        self.infos.insert(url.to_string(), output);
+        self
+    }
+
+    /// Replays `output` for youtube-dl searches of `search_term`, see
+    /// `SearchBackend::search_term`
+    pub fn with_search(mut self, search_term: &str, output: &'static str) -> Self {
+        self.searches.insert(search_term.to_string(), output);
+        self
+    }
+
+    /// Replays `output` for `youtube-dl -J --flat-playlist --playlist-end=1 <url>`
+    pub fn with_type(mut self, url: &str, output: &'static str) -> Self {
+        self.types.insert(url.to_string(), output);
+        self
+    }
+
+    /// Replays `first_output` for `youtube-dl -j --playlist-items=1 <url>` and `flat_output` for
+    /// `youtube-dl -j --flat-playlist <url>`. Selected items are picked from the full playlist.
+    pub fn with_playlist(
+        mut self,
+        url: &str,
+        first_output: &'static str,
+        flat_output: &'static str,
+    ) -> Self {
+        self.playlists
+            .insert(url.to_string(), (first_output, flat_output));
+        self
+    }
+
+    fn search_output(&self, search_term: &str) -> &'static str {
+        self.searches.get(search_term).copied().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for FakeMetadataProvider {
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String> {
+        parse_info(self.infos.get(url).copied().unwrap_or_default())
+    }
+
+    async fn get_search(&self, query: &str, backend: SearchBackend) -> Result<MediaInfo, String> {
+        parse_info(self.search_output(&backend.search_term(query, 1)))
+    }
+
+    async fn get_search_results(
+        &self,
+        query: &str,
+        backend: SearchBackend,
+        count: usize,
+    ) -> Result<Vec<MediaInfo>, String> {
+        Ok(parse_search(
+            self.search_output(&backend.search_term(query, count)),
+        ))
+    }
+
+    async fn is_playlist(&self, url: &str) -> Result<bool, String> {
+        parse_is_playlist(self.types.get(url).copied().unwrap_or_default())
+    }
+
+    async fn get_playlist(
+        &self,
+        url: &str,
+        items: Option<&PlaylistItems>,
+    ) -> Result<LinkedList<MediaInfo>, String> {
+        let (first_output, flat_output) = self.playlists.get(url).copied().unwrap_or_default();
+
+        let sources = parse_playlist(first_output, flat_output);
+
+        let Some(items) = items else {
+            return Ok(sources);
+        };
+
+        // Select the items like youtube-dl would, where the first song has the playlist info
+        let len = sources.len();
+        let playlist = sources.front().and_then(|info| info.playlist.clone());
+
+        let mut selected: LinkedList<MediaInfo> = sources
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| match items {
+                PlaylistItems::Ranges(ranges) => ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&(i + 1))),
+                PlaylistItems::Last(count) => i + count >= len,
+            })
+            .map(|(_, info)| info)
+            .collect();
+
+        if let Some(first) = selected.front_mut() {
+            first.playlist = playlist;
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Replays recorded ffprobe output instead of requesting links.
+///
+/// Unknown urls don't serve audio.
+#[derive(Default)]
+pub struct FakeUrlProbe {
+    streams: HashMap<String, &'static str>,
+}
+
+impl FakeUrlProbe {
+    /// Creates a probe that knows the radio stream in `src/media/fixtures`
+    pub fn new() -> Self {
+        FakeUrlProbe::default().with_stream(RADIO_URL, FFPROBE_RADIO_JSON)
+    }
+
+    /// Replays `output` for `ffprobe -show_format <url>`
+    pub fn with_stream(mut self, url: &str, output: &'static str) -> Self {
+        self.streams.insert(url.to_string(), output);
+        self
+    }
+}
+
+#[async_trait]
+impl UrlProbe for FakeUrlProbe {
+    /// Only links to addresses are checked, which doesn't need a lookup
+    async fn check_host(&self, url: &str) -> Result<(), String> {
+        if Url::parse(url).map_or(false, |url| url.domain().is_some()) {
+            return Ok(());
+        }
+
+        check_host(url).await.map(|_| ())
+    }
+
+    async fn is_audio_stream(&self, url: &str) -> bool {
+        self.streams.contains_key(url)
+    }
+
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String> {
+        match self.streams.get(url) {
+            Some(output) => parse_probe(output, url, MediaSource::Direct),
+            None => Err("Unable to play the file".to_string()),
+        }
+    }
+}
@@ -1,15 +1,26 @@
-use poise::serenity_prelude::{self as serenity, async_trait, GuildId};
-use songbird::input::{Input, Restartable};
+use poise::serenity_prelude::{self as serenity, async_trait, GuildId, UserId};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use songbird::input::Input;
 use songbird::tracks::TrackHandle;
 use songbird::{Call, Event, EventContext, EventHandler};
-use std::collections::{HashMap, LinkedList};
+use std::collections::{HashMap, HashSet, LinkedList};
 use std::sync::Arc;
-use std::time::Duration;
-use tracing::{error, info};
-
-use crate::message_context::MessageContext;
-
-use super::media_info::MediaInfo;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    config,
+    database::plugin::{QueueSnapshot, SnapshotSong},
+    message_context::MessageContext,
+    utils::strings,
+};
+
+use super::audio_cache;
+use super::audio_source::{self, AudioFilter, LOUDNORM_FILTER};
+use super::media_info::{MediaInfo, MediaSource, Requester};
+use super::metadata::MetadataProvider;
 
 struct MediaEventHandler {
     signaler: Arc<(async_std::sync::Mutex<bool>, async_std::sync::Condvar)>,
@@ -21,33 +32,493 @@ impl MediaEventHandler {
     }
 }
 
+#[derive(Clone)]
 pub struct MediaItem {
     pub info: MediaInfo,
     pub message_ctx: MessageContext,
+    pub requester: Requester,
+}
+
+impl MediaItem {
+    fn snapshot(&self) -> SnapshotSong {
+        SnapshotSong {
+            info: self.info.clone(),
+            requester: self.requester.clone(),
+        }
+    }
+}
+
+/// Result of a vote to skip the current song
+pub struct SkipVote {
+    /// Identifies the song the vote was for
+    pub track_number: usize,
+    pub votes: usize,
+    pub required: usize,
+    pub skipped: bool,
+}
+
+/// The current song and its playback state
+pub struct NowPlaying {
+    pub info: MediaInfo,
+    pub requester: Requester,
+    pub position: i64,
+    pub paused: bool,
+    pub filters: Vec<AudioFilter>,
+}
+
+/// Volume, normalization and filters of a guild, which its next player starts with
+#[derive(Clone, PartialEq, Debug)]
+pub struct AudioSettings {
+    pub volume: f32,
+    pub normalize: bool,
+    pub filters: Vec<AudioFilter>,
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LoopMode {
+    #[default]
+    #[name = "off"]
+    Off,
+    #[name = "track"]
+    Track,
+    #[name = "queue"]
+    Queue,
 }
 
 pub struct MediaQueue {
     pub running_state: bool,
     pub now_playing: Option<(MediaItem, TrackHandle)>,
     pub queue: LinkedList<Option<MediaItem>>,
+    /// When set, newly enqueued songs are inserted at a random position
+    pub shuffle_mode: bool,
+    /// When set, newly enqueued songs take turns between requesters
+    pub fair_mode: bool,
+    pub loop_mode: LoopMode,
+    /// Set by skip so that a looping track isn't replayed
+    pub skip_requested: bool,
+    /// Users who voted to skip the current song
+    pub skip_votes: HashSet<UserId>,
+    /// Counts the songs started, so votes for a song that ended are not counted
+    pub track_number: usize,
+    /// When the current song was paused, if it is paused
+    pub paused_at: Option<Instant>,
+    /// Volume of every track, where 1.0 is 100%
+    pub volume: f32,
+    /// Normalizes loudness with ffmpeg's loudnorm filter
+    pub normalize: bool,
+    pub filters: Vec<AudioFilter>,
+    /// Set to replay the current song from this position once it stops,
+    /// e.g. to rebuild its source with different filters
+    pub restart_at: Option<Duration>,
+    /// Where the current song was last started or seeked to. Time played since then may be
+    /// sped up by filters.
+    pub seeked_to: Duration,
+    /// Recently finished songs, latest first
+    pub played: LinkedList<MediaItem>,
+    /// When the player ran out of songs, if it is waiting for more
+    pub idle_since: Option<Instant>,
+}
+
+impl MediaQueue {
+    /// Inserts the item at a random position in the queue
+    fn push_random(&mut self, item: MediaItem) {
+        let index = rand::thread_rng().gen_range(0..=self.queue.len());
+
+        let mut tail = self.queue.split_off(index);
+        self.queue.push_back(Some(item));
+        self.queue.append(&mut tail);
+    }
+
+    fn push(&mut self, item: MediaItem) {
+        if self.fair_mode {
+            self.push_fair(item);
+        } else if self.shuffle_mode {
+            self.push_random(item);
+        } else {
+            self.queue.push_front(Some(item));
+        }
+    }
+
+    /// Inserts the item after every other requester's song of the same round, so that
+    /// requesters take turns instead of waiting for someone's whole playlist
+    fn push_fair(&mut self, item: MediaItem) {
+        let user_id = item.requester.user_id;
+        // the playing song is part of the first round
+        let playing = self
+            .now_playing
+            .as_ref()
+            .map(|(playing, _)| playing.requester.user_id);
+
+        // the item is in round n if its requester already has n songs queued
+        let round = self
+            .queue
+            .iter()
+            .flatten()
+            .filter(|queued| queued.requester.user_id == user_id)
+            .count()
+            + usize::from(playing == Some(user_id));
+
+        // find the first song, in play order, that belongs to a later round
+        let mut rounds: HashMap<UserId, usize> = playing.into_iter().map(|id| (id, 1)).collect();
+        let position = self.queue.iter().rev().position(|queued| match queued {
+            Some(queued) => {
+                let queued_round = rounds.entry(queued.requester.user_id).or_insert(0);
+                *queued_round += 1;
+
+                *queued_round > round + 1
+            }
+            None => false,
+        });
+
+        match position {
+            Some(position) => {
+                let mut next = self.queue.split_off(self.queue.len() - position);
+                self.queue.push_back(Some(item));
+                self.queue.append(&mut next);
+            }
+            None => self.queue.push_front(Some(item)),
+        }
+    }
+
+    /// Puts the queue in fair order, keeping the order of each requester's songs
+    fn arrange_fair(&mut self) {
+        let items = std::mem::take(&mut self.queue);
+
+        for item in items.into_iter().rev() {
+            match item {
+                Some(item) => self.push_fair(item),
+                None => self.queue.push_front(None),
+            }
+        }
+    }
+
+    fn shuffle(&mut self) {
+        let mut items = std::mem::take(&mut self.queue)
+            .into_iter()
+            .collect::<Vec<Option<MediaItem>>>();
+
+        items.shuffle(&mut rand::thread_rng());
+
+        self.queue = items.into_iter().collect();
+
+        // shuffle within the turns rather than across them
+        if self.fair_mode {
+            self.arrange_fair();
+        }
+    }
+
+    /// Removes the song at the 1-based position shown in the queue embed
+    fn remove(&mut self, position: usize) -> Result<MediaItem, String> {
+        let index = queue_index(position, self.queue.len())?;
+
+        let mut tail = self.queue.split_off(index);
+        let item = tail.pop_front().flatten();
+        self.queue.append(&mut tail);
+
+        item.ok_or_else(|| "No song at that position".to_string())
+    }
+
+    /// Moves the song at `from` so that it ends up at `to`. Both are 1-based queue positions.
+    fn move_item(&mut self, from: usize, to: usize) -> Result<MediaInfo, String> {
+        let len = self.queue.len();
+        let to_index = queue_index(to, len)?;
+        let item = self.remove(from)?;
+
+        let info = item.info.clone();
+
+        let mut tail = self.queue.split_off(to_index);
+        self.queue.push_back(Some(item));
+        self.queue.append(&mut tail);
+
+        Ok(info)
+    }
+
+    /// Swaps the songs at two 1-based queue positions
+    fn swap(&mut self, a: usize, b: usize) -> Result<(MediaInfo, MediaInfo), String> {
+        let a_index = queue_index(a, self.queue.len())?;
+        let b_index = queue_index(b, self.queue.len())?;
+
+        let mut items = std::mem::take(&mut self.queue)
+            .into_iter()
+            .collect::<Vec<Option<MediaItem>>>();
+
+        items.swap(a_index, b_index);
+
+        let infos = match (&items[a_index], &items[b_index]) {
+            (Some(a), Some(b)) => Ok((a.info.clone(), b.info.clone())),
+            _ => Err("No song at that position".to_string()),
+        };
+
+        self.queue = items.into_iter().collect();
+
+        infos
+    }
+
+    /// How much faster the song plays with the current filters
+    fn speed(&self) -> f64 {
+        self.filters.iter().map(AudioFilter::speed).product()
+    }
+
+    /// Converts a played position to the song's own time, which ffmpeg seeks in
+    fn song_time(&self, position: Duration) -> Duration {
+        self.seeked_to
+            + position
+                .saturating_sub(self.seeked_to)
+                .mul_f64(self.speed())
+    }
+
+    /// The ffmpeg audio filters to build new sources with
+    fn audio_filters(&self) -> Vec<String> {
+        let mut filters = self
+            .filters
+            .iter()
+            .map(|filter| filter.ffmpeg_filter().to_string())
+            .collect::<Vec<String>>();
+
+        // normalize last so that it also evens out the effects
+        if self.normalize {
+            filters.push(LOUDNORM_FILTER.to_string());
+        }
+
+        filters
+    }
+
+    /// Adds a finished item to the played stack, dropping the oldest if it is full
+    fn push_played(&mut self, item: MediaItem, max_size: usize) {
+        self.played.push_front(item);
+
+        while self.played.len() > max_size {
+            self.played.pop_back();
+        }
+    }
+
+    /// Puts a finished item back into the queue according to the loop mode
+    fn requeue_finished(&mut self, item: MediaItem, skipped: bool) {
+        match self.loop_mode {
+            LoopMode::Off => (),
+            LoopMode::Track if skipped => (),
+            LoopMode::Track => self.queue.push_back(Some(item)),
+            LoopMode::Queue => {
+                self.queue.push_front(Some(item));
+
+                if self.fair_mode {
+                    self.arrange_fair();
+                }
+            }
+        }
+    }
+
+    /// Handles the song that just stopped, replaying it if it is being restarted.
+    /// Returns whether it played to the end rather than being skipped or restarted.
+    fn finish_song(&mut self, item: MediaItem, played_size: usize) -> bool {
+        let skipped = std::mem::take(&mut self.skip_requested);
+
+        if self.restart_at.is_some() {
+            if !skipped {
+                self.queue.push_back(Some(item));
+                return false;
+            }
+
+            // a skip during the restart wins over it
+            self.restart_at = None;
+        }
+
+        self.push_played(item.clone(), played_size);
+        self.requeue_finished(item, skipped);
+
+        !skipped
+    }
+
+    /// Stops the current song without looping it
+    fn skip_current(&mut self) {
+        let track_handle = match &self.now_playing {
+            Some((_, track_handle)) => track_handle.clone(),
+            None => return,
+        };
+
+        self.skip_requested = true;
+
+        let result = track_handle.stop();
+        match result {
+            Ok(_) => (),
+            Err(x) => {
+                error!("Error skipping track: {:?}", x);
+            }
+        }
+    }
+
+    /// Adds a vote to skip the song identified by `track_number`, or the current song if None.
+    /// Returns the number of votes.
+    fn add_skip_vote(
+        &mut self,
+        user_id: UserId,
+        track_number: Option<usize>,
+    ) -> Result<usize, String> {
+        if track_number.map_or(false, |track_number| track_number != self.track_number) {
+            return Err("The song this vote was for already ended".to_string());
+        }
+
+        self.skip_votes.insert(user_id);
+
+        Ok(self.skip_votes.len())
+    }
+
+    /// Returns the total length of the queued songs and how many of them are live streams,
+    /// which are left out of the total
+    fn duration(&self) -> (i64, usize) {
+        self.queue
+            .iter()
+            .flatten()
+            .fold((0, 0), |(duration, live), item| {
+                if item.info.live {
+                    (duration, live + 1)
+                } else {
+                    (duration + item.info.duration, live)
+                }
+            })
+    }
+
+    /// Returns the current song followed by the queue, next song first
+    fn upcoming(&self) -> (Option<SnapshotSong>, Vec<SnapshotSong>) {
+        (
+            self.now_playing
+                .as_ref()
+                .map(|(media_item, _)| media_item.snapshot()),
+            self.queue
+                .iter()
+                .rev()
+                .flatten()
+                .map(MediaItem::snapshot)
+                .collect(),
+        )
+    }
+
+    /// Queues the songs of a snapshot ahead of the current queue, resuming the first song
+    /// at the saved position
+    fn restore(&mut self, snapshot: &QueueSnapshot, message_ctx: MessageContext) {
+        let songs = snapshot.now_playing.iter().chain(snapshot.queue.iter());
+
+        for song in songs.rev() {
+            self.queue.push_back(Some(MediaItem {
+                info: song.info.clone(),
+                message_ctx: message_ctx.clone(),
+                requester: song.requester.clone(),
+            }));
+        }
+
+        if snapshot.now_playing.is_some() && snapshot.position > 0 {
+            self.restart_at = Some(Duration::from_secs(snapshot.position as u64));
+        }
+    }
+
+    /// Flat playlist entries among the next `count` songs
+    fn partial_ahead(&self, count: usize) -> Vec<MediaInfo> {
+        self.queue
+            .iter()
+            .rev()
+            .take(count)
+            .flatten()
+            .filter(|item| item.info.partial)
+            .map(|item| item.info.clone())
+            .collect()
+    }
+
+    /// Replaces the flat entries of `url` with the full metadata, or removes them if the lookup
+    /// failed. Returns the titles of the removed songs.
+    fn hydrate(&mut self, url: &str, result: Result<MediaInfo, String>) -> Vec<String> {
+        let is_entry = |item: &Option<MediaItem>| matches!(item, Some(item) if item.info.partial && item.info.url == url);
+
+        match result {
+            Ok(info) => {
+                for item in self.queue.iter_mut().flatten() {
+                    if item.info.partial && item.info.url == url {
+                        item.info = MediaInfo {
+                            playlist: item.info.playlist.take(),
+                            ..info.clone()
+                        };
+                    }
+                }
+
+                vec![]
+            }
+            Err(_) => {
+                let (removed, kept): (LinkedList<_>, LinkedList<_>) =
+                    std::mem::take(&mut self.queue)
+                        .into_iter()
+                        .partition(is_entry);
+
+                self.queue = kept;
+
+                removed
+                    .into_iter()
+                    .flatten()
+                    .map(|item| item.info.title)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Converts a 1-based position as shown in the queue embed to an index of `MediaQueue::queue`.
+///
+/// Position 1 is the current song, so the queue starts at position 2. The queue is stored
+/// reversed, so position 2 is the back of the list.
+fn queue_index(position: usize, queue_len: usize) -> Result<usize, String> {
+    if position == 1 {
+        Err("Position 1 is the current song!".to_string())
+    } else if position == 0 || position > queue_len + 1 {
+        Err(format!(
+            "Position {} is out of range! The queue has {} song(s).",
+            position,
+            queue_len + 1
+        ))
+    } else {
+        Ok(queue_len + 1 - position)
+    }
+}
+
+/// Reads the playback position of a track
+async fn track_position(track_handle: &TrackHandle) -> Result<Duration, String> {
+    match track_handle.get_info().await {
+        Ok(trackstate) => Ok(trackstate.position),
+        Err(trackerror) => Err(format!(
+            "Unable to get current song info from Track: {}",
+            trackerror
+        )),
+    }
 }
 
 pub struct ChannelMediaPlayer {
     pub guild_id: GuildId,
+    /// Channel of the message that started the player, used for notices
+    pub message_ctx: MessageContext,
     pub lock_protected_media_queue: (async_std::sync::Mutex<MediaQueue>, async_std::sync::Condvar),
+    /// Set when upcoming songs may need their full metadata looked up
+    hydration_signaler: (async_std::sync::Mutex<bool>, async_std::sync::Condvar),
+    /// Task that leaves the channel once the queue stays empty, stopped when the player quits
+    idle_watcher: std::sync::Mutex<Option<JoinHandle<()>>>,
+    /// Task that leaves the channel once the bot was alone for the alone timeout. Replaced on
+    /// every voice state update, so only the latest departure counts.
+    alone_timer: std::sync::Mutex<Option<JoinHandle<()>>>,
 }
 
 type GuildMediaPlayerMap = async_std::sync::Mutex<
     Option<HashMap<serenity::model::prelude::GuildId, Arc<ChannelMediaPlayer>>>,
 >;
 
+type GuildAudioSettingsMap =
+    async_std::sync::Mutex<Option<HashMap<serenity::model::prelude::GuildId, AudioSettings>>>;
+
 pub struct GlobalMediaPlayer {
     pub guild_media_player_map: GuildMediaPlayerMap,
+    /// Audio settings of guilds whose player quit, so they are kept when the bot joins again
+    guild_audio_settings_map: GuildAudioSettingsMap,
 }
 
 impl GlobalMediaPlayer {
     pub const UNINITIALIZED: GlobalMediaPlayer = GlobalMediaPlayer {
         guild_media_player_map: async_std::sync::Mutex::new(None),
+        guild_audio_settings_map: async_std::sync::Mutex::new(None),
     };
 
     pub async fn init_self(&self) {
@@ -56,12 +527,32 @@ impl GlobalMediaPlayer {
             Some(_) => panic!("HashMap should be uninitialized!"),
             None => *guild_map = Some(HashMap::new()),
         };
+
+        *self.guild_audio_settings_map.lock().await = Some(HashMap::new());
+    }
+
+    /// Gives a new player the audio settings its guild had when its last player quit
+    async fn restore_audio_settings(&self, guild_id: GuildId, media_player: &ChannelMediaPlayer) {
+        let settings = self
+            .guild_audio_settings_map
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()
+            .get(&guild_id)
+            .cloned();
+
+        if let Some(settings) = settings {
+            media_player.set_audio_settings(settings).await;
+        }
     }
 
     pub async fn start(
         &self,
         guild_id: GuildId,
         voice_channel_handler: Arc<serenity::Mutex<Call>>,
+        message_ctx: MessageContext,
+        metadata: Arc<dyn MetadataProvider>,
     ) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
@@ -71,12 +562,39 @@ impl GlobalMediaPlayer {
                 "Already connected to a voice channel in this server!",
             ));
         } else {
-            guild_map.insert(
+            let media_player = ChannelMediaPlayer::create_and_initialize(
                 guild_id,
-                ChannelMediaPlayer::create_and_initialize(guild_id, voice_channel_handler),
+                voice_channel_handler,
+                message_ctx,
+                metadata,
             );
+            self.restore_audio_settings(guild_id, &media_player).await;
+            guild_map.insert(guild_id, media_player);
+        }
+
+        Ok(())
+    }
+
+    /// Starts a player that only manages its queue, for tests without a voice connection
+    #[cfg(test)]
+    pub async fn start_without_voice(
+        &self,
+        guild_id: GuildId,
+        message_ctx: MessageContext,
+    ) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if guild_map.contains_key(&guild_id) {
+            return Err(String::from(
+                "Already connected to a voice channel in this server!",
+            ));
         }
 
+        let media_player = ChannelMediaPlayer::create(guild_id, message_ctx);
+        self.restore_audio_settings(guild_id, &media_player).await;
+        guild_map.insert(guild_id, media_player);
+
         Ok(())
     }
 
@@ -92,11 +610,43 @@ impl GlobalMediaPlayer {
         }
     }
 
+    /// Votes to skip the current song, or the song identified by `track_number`.
+    ///
+    /// The song is skipped once `required` users voted, or right away if the user is a DJ or
+    /// requested the song.
+    pub async fn vote_skip(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        is_dj: bool,
+        required: usize,
+        track_number: Option<usize>,
+    ) -> Result<SkipVote, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player
+                .vote_skip(user_id, is_dj, required, track_number)
+                .await
+        } else {
+            Err(String::from("Not connected to a voice channel!"))
+        }
+    }
+
     pub async fn quit(&self, guild_id: GuildId) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
         if let Some(media_player) = guild_map.remove(&guild_id) {
+            let settings = media_player.audio_settings().await;
+            self.guild_audio_settings_map
+                .lock()
+                .await
+                .as_mut()
+                .unwrap()
+                .insert(guild_id, settings);
+
             media_player.quit().await;
             Ok(())
         } else {
@@ -104,6 +654,60 @@ impl GlobalMediaPlayer {
         }
     }
 
+    /// Keeps the idle watcher of the guild's player so it is stopped when the player quits
+    pub async fn set_idle_watcher(
+        &self,
+        guild_id: GuildId,
+        idle_watcher: JoinHandle<()>,
+    ) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            let previous = media_player
+                .idle_watcher
+                .lock()
+                .unwrap()
+                .replace(idle_watcher);
+
+            if let Some(previous) = previous {
+                previous.abort();
+            }
+
+            Ok(())
+        } else {
+            idle_watcher.abort();
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Replaces the alone timer of the guild's player, stopping the previous one. None only
+    /// stops it.
+    pub async fn set_alone_timer(
+        &self,
+        guild_id: GuildId,
+        alone_timer: Option<JoinHandle<()>>,
+    ) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            let previous =
+                std::mem::replace(&mut *media_player.alone_timer.lock().unwrap(), alone_timer);
+
+            if let Some(previous) = previous {
+                previous.abort();
+            }
+
+            Ok(())
+        } else {
+            if let Some(alone_timer) = alone_timer {
+                alone_timer.abort();
+            }
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
     /// Reads the queue between start and length.
     ///
     /// Returns a tuple of the queue as a LinkedList and the total size of the queue
@@ -112,7 +716,7 @@ impl GlobalMediaPlayer {
         guild_id: GuildId,
         start: usize,
         length: usize,
-    ) -> Result<(LinkedList<MediaInfo>, usize), String> {
+    ) -> Result<(LinkedList<(MediaInfo, Option<Requester>)>, usize), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
@@ -134,7 +738,7 @@ impl GlobalMediaPlayer {
         }
     }
 
-    pub async fn now_playing(&self, guild_id: GuildId) -> Result<Option<(MediaInfo, i64)>, String> {
+    pub async fn now_playing(&self, guild_id: GuildId) -> Result<Option<NowPlaying>, String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
@@ -150,12 +754,13 @@ impl GlobalMediaPlayer {
         guild_id: GuildId,
         info: MediaInfo,
         message_ctx: MessageContext,
+        requester: Requester,
     ) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
         if let Some(media_player) = guild_map.get(&guild_id) {
-            media_player.enqueue(info, message_ctx).await;
+            media_player.enqueue(info, message_ctx, requester).await;
         } else {
             return Err("Not connected to a voice channel!".to_string());
         }
@@ -168,12 +773,15 @@ impl GlobalMediaPlayer {
         guild_id: GuildId,
         infos: LinkedList<MediaInfo>,
         message_ctx: MessageContext,
+        requester: Requester,
     ) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
         if let Some(media_player) = guild_map.get(&guild_id) {
-            media_player.enqueue_batch(infos, message_ctx).await;
+            media_player
+                .enqueue_batch(infos, message_ctx, requester)
+                .await;
         } else {
             return Err("Not connected to a voice channel!".to_string());
         }
@@ -186,12 +794,15 @@ impl GlobalMediaPlayer {
         guild_id: GuildId,
         info: MediaInfo,
         message_ctx: MessageContext,
+        requester: Requester,
     ) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
         if let Some(media_player) = guild_map.get(&guild_id) {
-            media_player.enqueue_next(info, message_ctx).await;
+            media_player
+                .enqueue_next(info, message_ctx, requester)
+                .await;
         } else {
             return Err("Not connected to a voice channel!".to_string());
         }
@@ -199,87 +810,469 @@ impl GlobalMediaPlayer {
         Ok(())
     }
 
-    pub async fn clear(
-        &self,
-        guild_id: GuildId,
-    ) -> Result<(), String> {
+    pub async fn shuffle(&self, guild_id: GuildId) -> Result<(), String> {
         let mut guild_map_guard = self.guild_media_player_map.lock().await;
         let guild_map = guild_map_guard.as_mut().unwrap();
 
         if let Some(media_player) = guild_map.get(&guild_id) {
-            media_player.clear().await;
+            media_player.shuffle().await;
         } else {
             return Err("Not connected to a voice channel!".to_string());
         }
 
         Ok(())
     }
-}
-
-#[async_trait]
-impl EventHandler for MediaEventHandler {
-    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
-        let (mutex, condvar) = &*self.signaler;
 
-        let mut guard = mutex.lock().await;
-        *guard = true;
-        condvar.notify_one();
+    /// Sets whether requesters take turns in the queue. The queue is rearranged when enabled.
+    ///
+    /// If `enabled` is None, the mode is toggled. Returns the new mode.
+    pub async fn set_fair_mode(
+        &self,
+        guild_id: GuildId,
+        enabled: Option<bool>,
+    ) -> Result<bool, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
 
-        None
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.set_fair_mode(enabled).await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
     }
-}
 
-impl ChannelMediaPlayer {
-    fn create_and_initialize(
+    /// Sets whether songs enqueued later are inserted at random positions.
+    ///
+    /// If `enabled` is None, the mode is toggled. Returns the new mode.
+    pub async fn set_shuffle_mode(
+        &self,
         guild_id: GuildId,
-        voice_channel_handler: Arc<serenity::Mutex<Call>>,
-    ) -> Arc<Self> {
-        let media_player = Arc::new(ChannelMediaPlayer {
-            guild_id,
-            lock_protected_media_queue: (
-                async_std::sync::Mutex::new(MediaQueue {
-                    running_state: true,
-                    now_playing: None,
-                    queue: LinkedList::new(),
-                }),
-                async_std::sync::Condvar::new(),
-            ),
-        });
+        enabled: Option<bool>,
+    ) -> Result<bool, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
 
-        tokio::spawn(Self::media_player_run(
-            voice_channel_handler,
-            media_player.clone(),
-        ));
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.set_shuffle_mode(enabled).await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
 
-        media_player
+    /// Pauses the current song. Returns the time it was paused at.
+    pub async fn pause(&self, guild_id: GuildId) -> Result<Instant, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.pause().await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
     }
 
-    async fn skip(&self) {
-        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
-        let smq_locked = shared_media_queue_lock.lock().await;
-        match &smq_locked.now_playing {
-            Some((_, track_handle)) => {
-                let result = track_handle.stop();
-                match result {
-                    Ok(_) => (),
-                    Err(x) => {
-                        error!("Error skipping track: {:?}", x);
-                    }
-                }
-            }
-            None => (),
+    pub async fn resume(&self, guild_id: GuildId) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.resume().await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn paused_at(&self, guild_id: GuildId) -> Result<Option<Instant>, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.paused_at().await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Sets the volume of the current and all later songs. 1.0 is 100%.
+    pub async fn set_volume(&self, guild_id: GuildId, volume: f32) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.set_volume(volume).await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn volume(&self, guild_id: GuildId) -> Result<f32, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.volume().await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Sets whether later songs are loudness normalized.
+    ///
+    /// If `enabled` is None, the mode is toggled. Returns the new mode.
+    pub async fn set_normalize(
+        &self,
+        guild_id: GuildId,
+        enabled: Option<bool>,
+    ) -> Result<bool, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.set_normalize(enabled).await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Toggles an effect filter, or clears all filters if `filter` is None.
+    /// The current song is restarted at its position with the new filters.
+    ///
+    /// Returns the active filters.
+    pub async fn toggle_filter(
+        &self,
+        guild_id: GuildId,
+        filter: Option<AudioFilter>,
+    ) -> Result<Vec<AudioFilter>, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.toggle_filter(filter).await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Queues the last finished song to play next. Returns the queued song.
+    pub async fn previous(&self, guild_id: GuildId) -> Result<MediaInfo, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.previous().await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Restarts the current song from the beginning
+    pub async fn replay(&self, guild_id: GuildId) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.replay().await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Reads the songs played in this session between start and length, latest first.
+    ///
+    /// Returns a tuple of the songs as a LinkedList and the total number of played songs
+    pub async fn read_played(
+        &self,
+        guild_id: GuildId,
+        start: usize,
+        length: usize,
+    ) -> Result<(LinkedList<MediaInfo>, usize), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.read_played(start, length).await)
+        } else {
+            Err(String::from("Not connected to a voice channel!"))
+        }
+    }
+
+    /// Returns when the player ran out of songs, or None if it is playing
+    pub async fn idle_since(&self, guild_id: GuildId) -> Result<Option<Instant>, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.idle_since().await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn message_ctx(&self, guild_id: GuildId) -> Result<MessageContext, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.message_ctx.clone())
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Removes the song at a 1-based queue position. Returns the removed song.
+    pub async fn remove(&self, guild_id: GuildId, position: usize) -> Result<MediaInfo, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.remove(position).await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Moves a song between 1-based queue positions. Returns the moved song.
+    pub async fn move_item(
+        &self,
+        guild_id: GuildId,
+        from: usize,
+        to: usize,
+    ) -> Result<MediaInfo, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.move_item(from, to).await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Swaps the songs at two 1-based queue positions. Returns the songs now at `a` and `b`.
+    pub async fn swap(
+        &self,
+        guild_id: GuildId,
+        a: usize,
+        b: usize,
+    ) -> Result<(MediaInfo, MediaInfo), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.swap(a, b).await
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn set_loop_mode(&self, guild_id: GuildId, mode: LoopMode) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.set_loop_mode(mode).await;
+        } else {
+            return Err("Not connected to a voice channel!".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the total length of the queue and how many live streams are left out of it
+    pub async fn queue_duration(&self, guild_id: GuildId) -> Result<(i64, usize), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.queue_duration().await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn loop_mode(&self, guild_id: GuildId) -> Result<LoopMode, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.loop_mode().await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    /// Returns the guilds with a running media player
+    pub async fn guild_ids(&self) -> Vec<GuildId> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        guild_map.keys().cloned().collect()
+    }
+
+    pub async fn snapshot(
+        &self,
+        guild_id: GuildId,
+        voice_channel: serenity::ChannelId,
+    ) -> Result<QueueSnapshot, String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            Ok(media_player.snapshot(voice_channel).await)
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn restore(&self, snapshot: &QueueSnapshot) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&snapshot.guild_id) {
+            media_player.restore(snapshot).await;
+            Ok(())
+        } else {
+            Err("Not connected to a voice channel!".to_string())
+        }
+    }
+
+    pub async fn clear(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<(), String> {
+        let mut guild_map_guard = self.guild_media_player_map.lock().await;
+        let guild_map = guild_map_guard.as_mut().unwrap();
+
+        if let Some(media_player) = guild_map.get(&guild_id) {
+            media_player.clear().await;
+        } else {
+            return Err("Not connected to a voice channel!".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventHandler for MediaEventHandler {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let (mutex, condvar) = &*self.signaler;
+
+        let mut guard = mutex.lock().await;
+        *guard = true;
+        condvar.notify_one();
+
+        None
+    }
+}
+
+impl ChannelMediaPlayer {
+    fn create_and_initialize(
+        guild_id: GuildId,
+        voice_channel_handler: Arc<serenity::Mutex<Call>>,
+        message_ctx: MessageContext,
+        metadata: Arc<dyn MetadataProvider>,
+    ) -> Arc<Self> {
+        let media_player = Self::create(guild_id, message_ctx);
+
+        tokio::spawn(Self::media_player_run(
+            voice_channel_handler,
+            media_player.clone(),
+        ));
+
+        tokio::spawn(Self::hydration_run(media_player.clone(), metadata));
+
+        media_player
+    }
+
+    /// Creates a player with an empty queue, without starting playback
+    fn create(guild_id: GuildId, message_ctx: MessageContext) -> Arc<Self> {
+        Arc::new(ChannelMediaPlayer {
+            guild_id,
+            message_ctx,
+            lock_protected_media_queue: (
+                async_std::sync::Mutex::new(MediaQueue {
+                    running_state: true,
+                    now_playing: None,
+                    queue: LinkedList::new(),
+                    shuffle_mode: false,
+                    fair_mode: false,
+                    loop_mode: LoopMode::Off,
+                    skip_requested: false,
+                    skip_votes: HashSet::new(),
+                    track_number: 0,
+                    paused_at: None,
+                    volume: 1.0,
+                    normalize: false,
+                    filters: vec![],
+                    restart_at: None,
+                    seeked_to: Duration::ZERO,
+                    played: LinkedList::new(),
+                    idle_since: None,
+                }),
+                async_std::sync::Condvar::new(),
+            ),
+            hydration_signaler: (
+                async_std::sync::Mutex::new(false),
+                async_std::sync::Condvar::new(),
+            ),
+            idle_watcher: std::sync::Mutex::new(None),
+            alone_timer: std::sync::Mutex::new(None),
+        })
+    }
+
+    async fn skip(&self) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        shared_media_queue_lock.lock().await.skip_current();
+    }
+
+    async fn vote_skip(
+        &self,
+        user_id: UserId,
+        is_dj: bool,
+        required: usize,
+        track_number: Option<usize>,
+    ) -> Result<SkipVote, String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        let is_requester = match &smq_locked.now_playing {
+            Some((media_item, _)) => media_item.requester.user_id == user_id,
+            None => return Err("No songs playing!".to_string()),
         };
+
+        let votes = smq_locked.add_skip_vote(user_id, track_number)?;
+        let skipped = is_dj || is_requester || votes >= required;
+
+        if skipped {
+            smq_locked.skip_current();
+        }
+
+        Ok(SkipVote {
+            track_number: smq_locked.track_number,
+            votes,
+            required,
+            skipped,
+        })
     }
 
     async fn seek(&self, time: i64) -> Result<(), String> {
         let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
-        let smq_locked = shared_media_queue_lock.lock().await;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
 
         match &smq_locked.now_playing {
+            Some((media_item, _)) if media_item.info.live => {
+                Err(String::from("Cannot seek, live streams can't be seeked."))
+            }
             Some((media_item, track_handle)) => {
                 if time < media_item.info.duration {
-                    match track_handle.seek_time(Duration::from_secs(time as u64)) {
-                        Ok(_) => Ok(()),
+                    let time = Duration::from_secs(time as u64);
+
+                    match track_handle.seek_time(time) {
+                        Ok(_) => {
+                            smq_locked.seeked_to = time;
+                            Ok(())
+                        }
                         Err(err) => Err(format!("Unable to seek track: {:?}", err)),
                     }
                 } else {
@@ -290,28 +1283,224 @@ impl ChannelMediaPlayer {
         }
     }
 
-    async fn now_playing(&self) -> Result<Option<(MediaInfo, i64)>, String> {
+    async fn pause(&self) -> Result<Instant, String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        if smq_locked.paused_at.is_some() {
+            return Err(String::from("Already paused!"));
+        }
+
+        match &smq_locked.now_playing {
+            Some((_, track_handle)) => {
+                if let Err(err) = track_handle.pause() {
+                    return Err(format!("Unable to pause track: {:?}", err));
+                }
+            }
+            None => return Err(String::from("Cannot pause, not playing a song.")),
+        }
+
+        let paused_at = Instant::now();
+        smq_locked.paused_at = Some(paused_at);
+
+        Ok(paused_at)
+    }
+
+    async fn resume(&self) -> Result<(), String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        if smq_locked.paused_at.is_none() {
+            return Err(String::from("Not paused!"));
+        }
+
+        match &smq_locked.now_playing {
+            Some((_, track_handle)) => {
+                if let Err(err) = track_handle.play() {
+                    return Err(format!("Unable to resume track: {:?}", err));
+                }
+            }
+            None => return Err(String::from("Cannot resume, not playing a song.")),
+        }
+
+        smq_locked.paused_at = None;
+
+        Ok(())
+    }
+
+    async fn set_volume(&self, volume: f32) -> Result<(), String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.volume = volume;
+
+        if let Some((_, track_handle)) = &smq_locked.now_playing {
+            if let Err(err) = track_handle.set_volume(volume) {
+                return Err(format!("Unable to set volume: {:?}", err));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn volume(&self) -> f32 {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        shared_media_queue_lock.lock().await.volume
+    }
+
+    async fn set_normalize(&self, enabled: Option<bool>) -> bool {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.normalize = enabled.unwrap_or(!smq_locked.normalize);
+
+        smq_locked.normalize
+    }
+
+    async fn audio_settings(&self) -> AudioSettings {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let smq_locked = shared_media_queue_lock.lock().await;
+
+        AudioSettings {
+            volume: smq_locked.volume,
+            normalize: smq_locked.normalize,
+            filters: smq_locked.filters.clone(),
+        }
+    }
+
+    async fn set_audio_settings(&self, settings: AudioSettings) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.volume = settings.volume;
+        smq_locked.normalize = settings.normalize;
+        smq_locked.filters = settings.filters;
+    }
+
+    async fn toggle_filter(&self, filter: Option<AudioFilter>) -> Result<Vec<AudioFilter>, String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        // read with the old filters, which the song was played with so far
+        let restart_at = match &smq_locked.now_playing {
+            Some((_, track_handle)) => {
+                let track_handle = track_handle.clone();
+                let position = track_position(&track_handle).await?;
+                Some((track_handle, smq_locked.song_time(position)))
+            }
+            None => None,
+        };
+
+        match filter {
+            Some(filter) => {
+                if let Some(index) = smq_locked.filters.iter().position(|f| *f == filter) {
+                    smq_locked.filters.remove(index);
+                } else {
+                    smq_locked.filters.push(filter);
+                }
+            }
+            None => smq_locked.filters.clear(),
+        }
+
+        if let Some((track_handle, position)) = restart_at {
+            smq_locked.restart_at = Some(position);
+
+            if let Err(err) = track_handle.stop() {
+                smq_locked.restart_at = None;
+                return Err(format!("Unable to restart track: {:?}", err));
+            }
+        }
+
+        Ok(smq_locked.filters.clone())
+    }
+
+    async fn previous(&self) -> Result<MediaInfo, String> {
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        let Some(media_item) = smq_locked.played.pop_front() else {
+            return Err(String::from("No songs have been played yet!"));
+        };
+
+        let info = media_item.info.clone();
+
+        smq_locked.queue.push_back(Some(media_item));
+
+        shared_media_queue_condvar.notify_one();
+
+        Ok(info)
+    }
+
+    async fn replay(&self) -> Result<(), String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        match &smq_locked.now_playing {
+            Some((_, track_handle)) => match track_handle.seek_time(Duration::ZERO) {
+                Ok(_) => {
+                    smq_locked.seeked_to = Duration::ZERO;
+                    Ok(())
+                }
+                Err(err) => Err(format!("Unable to replay track: {:?}", err)),
+            },
+            None => Err(String::from("Cannot replay, not playing a song.")),
+        }
+    }
+
+    async fn read_played(&self, start: usize, length: usize) -> (LinkedList<MediaInfo>, usize) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let smq_locked = shared_media_queue_lock.lock().await;
+
+        (
+            smq_locked
+                .played
+                .iter()
+                .skip(start)
+                .take(length)
+                .map(|media_item| media_item.info.clone())
+                .collect(),
+            smq_locked.played.len(),
+        )
+    }
+
+    async fn idle_since(&self) -> Option<Instant> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        shared_media_queue_lock.lock().await.idle_since
+    }
+
+    async fn paused_at(&self) -> Option<Instant> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        shared_media_queue_lock.lock().await.paused_at
+    }
+
+    async fn now_playing(&self) -> Result<Option<NowPlaying>, String> {
         let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
         let smq_locked = shared_media_queue_lock.lock().await;
         match &smq_locked.now_playing {
             Some((media_item, track_handle)) => {
-                let result = track_handle.get_info().await;
-                match result {
-                    Ok(trackstate) => {
-                        let position = trackstate.position.as_secs() as i64;
-                        Ok(Some((media_item.info.clone(), position)))
-                    }
-                    Err(trackerror) => Err(format!(
-                        "Unable to get current song info from Track: {}",
-                        trackerror
-                    )),
-                }
+                let position = track_position(track_handle).await?;
+
+                Ok(Some(NowPlaying {
+                    info: media_item.info.clone(),
+                    requester: media_item.requester.clone(),
+                    position: smq_locked.song_time(position).as_secs() as i64,
+                    paused: smq_locked.paused_at.is_some(),
+                    filters: smq_locked.filters.clone(),
+                }))
             }
             None => Ok(None),
         }
     }
 
-    async fn read_queue(&self, start: usize, length: usize) -> (LinkedList<MediaInfo>, usize) {
+    async fn read_queue(
+        &self,
+        start: usize,
+        length: usize,
+    ) -> (LinkedList<(MediaInfo, Option<Requester>)>, usize) {
         let mut return_queue = LinkedList::new();
 
         let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
@@ -321,9 +1510,10 @@ impl ChannelMediaPlayer {
         let (start, length) = if start == 0 {
             match &smq_locked.now_playing {
                 Some((media_item, _)) => {
-                    return_queue.push_front(media_item.info.clone());
+                    return_queue
+                        .push_front((media_item.info.clone(), Some(media_item.requester.clone())));
                 }
-                None => return_queue.push_front(MediaInfo::empty()),
+                None => return_queue.push_front((MediaInfo::empty(), None)),
             }
             (start, length - 1)
         } else {
@@ -337,8 +1527,9 @@ impl ChannelMediaPlayer {
 
             if i >= start {
                 match media_item {
-                    Some(media_item) => return_queue.push_back(media_item.info.clone()),
-                    None => return_queue.push_back(MediaInfo::empty()),
+                    Some(media_item) => return_queue
+                        .push_back((media_item.info.clone(), Some(media_item.requester.clone()))),
+                    None => return_queue.push_back((MediaInfo::empty(), None)),
                 }
             }
         }
@@ -353,58 +1544,184 @@ impl ChannelMediaPlayer {
         )
     }
 
-    async fn enqueue(&self, info: MediaInfo, message_ctx: MessageContext) {
-        let (shared_media_queue_lock, shared_media_queue_condvar) =
-            &self.lock_protected_media_queue;
+    async fn enqueue(&self, info: MediaInfo, message_ctx: MessageContext, requester: Requester) {
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+
+        info!("Enqueuing song: {}", &info.title);
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.push(MediaItem {
+            info,
+            message_ctx,
+            requester,
+        });
+
+        shared_media_queue_condvar.notify_one();
+    }
+
+    async fn enqueue_next(
+        &self,
+        info: MediaInfo,
+        message_ctx: MessageContext,
+        requester: Requester,
+    ) {
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+
+        info!("Enqueuing song next: {}", &info.title);
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.queue.push_back(Some(MediaItem {
+            info,
+            message_ctx,
+            requester,
+        }));
+
+        shared_media_queue_condvar.notify_one();
+    }
+
+    async fn enqueue_batch(
+        &self,
+        mut media_infos: LinkedList<MediaInfo>,
+        message_ctx: MessageContext,
+        requester: Requester,
+    ) {
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        loop {
+            let media_info = match media_infos.pop_front() {
+                Some(x) => x,
+                None => break,
+            };
+            smq_locked.push(MediaItem {
+                info: media_info,
+                message_ctx: message_ctx.clone(),
+                requester: requester.clone(),
+            });
+        }
+
+        shared_media_queue_condvar.notify_one();
+
+        self.signal_hydration().await;
+    }
+
+    async fn shuffle(&self) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        info!("Shuffling queue of {} songs", smq_locked.queue.len());
+
+        smq_locked.shuffle();
+    }
+
+    async fn set_fair_mode(&self, enabled: Option<bool>) -> bool {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.fair_mode = enabled.unwrap_or(!smq_locked.fair_mode);
+
+        if smq_locked.fair_mode {
+            smq_locked.arrange_fair();
+        }
+
+        smq_locked.fair_mode
+    }
+
+    async fn set_shuffle_mode(&self, enabled: Option<bool>) -> bool {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.shuffle_mode = enabled.unwrap_or(!smq_locked.shuffle_mode);
+
+        smq_locked.shuffle_mode
+    }
+
+    async fn remove(&self, position: usize) -> Result<MediaInfo, String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.remove(position).map(|item| item.info)
+    }
+
+    async fn move_item(&self, from: usize, to: usize) -> Result<MediaInfo, String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
 
-        info!("Enqueuing song: {}", &info.title);
+        smq_locked.move_item(from, to)
+    }
+
+    async fn swap(&self, a: usize, b: usize) -> Result<(MediaInfo, MediaInfo), String> {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
 
         let mut smq_locked = shared_media_queue_lock.lock().await;
 
-        smq_locked
-            .queue
-            .push_front(Some(MediaItem { info, message_ctx }));
+        smq_locked.swap(a, b)
+    }
 
-        shared_media_queue_condvar.notify_one();
+    async fn set_loop_mode(&self, mode: LoopMode) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+
+        let mut smq_locked = shared_media_queue_lock.lock().await;
+
+        smq_locked.loop_mode = mode;
     }
 
-    async fn enqueue_next(&self, info: MediaInfo, message_ctx: MessageContext) {
-        let (shared_media_queue_lock, shared_media_queue_condvar) =
-            &self.lock_protected_media_queue;
+    async fn queue_duration(&self) -> (i64, usize) {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
 
-        info!("Enqueuing song next: {}", &info.title);
+        shared_media_queue_lock.lock().await.duration()
+    }
 
-        let mut smq_locked = shared_media_queue_lock.lock().await;
+    async fn loop_mode(&self) -> LoopMode {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
 
-        smq_locked
-            .queue
-            .push_back(Some(MediaItem { info, message_ctx }));
+        shared_media_queue_lock.lock().await.loop_mode
+    }
 
-        shared_media_queue_condvar.notify_one();
+    async fn snapshot(&self, voice_channel: serenity::ChannelId) -> QueueSnapshot {
+        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let smq_locked = shared_media_queue_lock.lock().await;
+
+        let position = match &smq_locked.now_playing {
+            Some((_, track_handle)) => track_position(track_handle).await.map_or(0, |position| {
+                smq_locked.song_time(position).as_secs() as i64
+            }),
+            None => 0,
+        };
+        let (now_playing, queue) = smq_locked.upcoming();
+
+        QueueSnapshot {
+            guild_id: self.guild_id,
+            voice_channel,
+            text_channel: self.message_ctx.channel,
+            now_playing,
+            position,
+            queue,
+        }
     }
 
-    async fn enqueue_batch(
-        &self,
-        mut media_infos: LinkedList<MediaInfo>,
-        message_ctx: MessageContext,
-    ) {
+    async fn restore(&self, snapshot: &QueueSnapshot) {
         let (shared_media_queue_lock, shared_media_queue_condvar) =
             &self.lock_protected_media_queue;
 
         let mut smq_locked = shared_media_queue_lock.lock().await;
 
-        loop {
-            let media_info = match media_infos.pop_front() {
-                Some(x) => x,
-                None => break,
-            };
-            smq_locked.queue.push_front(Some(MediaItem {
-                info: media_info,
-                message_ctx: message_ctx.clone(),
-            }));
-        }
+        smq_locked.restore(snapshot, self.message_ctx.clone());
 
         shared_media_queue_condvar.notify_one();
+
+        self.signal_hydration().await;
     }
 
     async fn clear(&self) {
@@ -419,7 +1736,15 @@ impl ChannelMediaPlayer {
     }
 
     async fn quit(&self) {
-        let (shared_media_queue_lock, _) = &self.lock_protected_media_queue;
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+
+        if let Some(idle_watcher) = self.idle_watcher.lock().unwrap().take() {
+            idle_watcher.abort();
+        }
+        if let Some(alone_timer) = self.alone_timer.lock().unwrap().take() {
+            alone_timer.abort();
+        }
 
         {
             let mut shared_media_queue = shared_media_queue_lock.lock().await;
@@ -427,6 +1752,9 @@ impl ChannelMediaPlayer {
             shared_media_queue.running_state = false;
             shared_media_queue.queue.push_front(None);
 
+            // wake up the media player if it is waiting for songs
+            shared_media_queue_condvar.notify_one();
+
             match &shared_media_queue.now_playing {
                 Some((_, track_handle)) => {
                     let res = track_handle.stop();
@@ -440,6 +1768,98 @@ impl ChannelMediaPlayer {
                 None => (),
             }
         }
+
+        self.signal_hydration().await;
+    }
+
+    async fn signal_hydration(&self) {
+        let (lock, condvar) = &self.hydration_signaler;
+        *lock.lock().await = true;
+        condvar.notify_one();
+    }
+
+    /// Looks up the full metadata of flat playlist entries before they are played, and drops
+    /// the ones that can't be resolved
+    async fn hydration_run(
+        shared_channel_media_player: Arc<ChannelMediaPlayer>,
+        metadata: Arc<dyn MetadataProvider>,
+    ) {
+        let (signal_lock, signal_condvar) = &shared_channel_media_player.hydration_signaler;
+
+        loop {
+            {
+                let mut signaled = signal_lock.lock().await;
+                while !*signaled {
+                    signaled = signal_condvar.wait(signaled).await;
+                }
+                *signaled = false;
+            }
+
+            let (shared_media_queue_lock, _) =
+                &shared_channel_media_player.lock_protected_media_queue;
+            if !shared_media_queue_lock.lock().await.running_state {
+                break;
+            }
+
+            while shared_channel_media_player
+                .hydrate_next(metadata.as_ref())
+                .await
+            {}
+        }
+    }
+
+    /// Looks up the next few flat entries. Returns false if there were none.
+    async fn hydrate_next(&self, metadata: &dyn MetadataProvider) -> bool {
+        let (shared_media_queue_lock, shared_media_queue_condvar) =
+            &self.lock_protected_media_queue;
+
+        let partial = {
+            let smq_locked = shared_media_queue_lock.lock().await;
+
+            if !smq_locked.running_state {
+                return false;
+            }
+
+            smq_locked.partial_ahead(config::queue::hydrate_ahead(self.guild_id))
+        };
+
+        if partial.is_empty() {
+            return false;
+        }
+
+        let mut dropped = vec![];
+
+        for info in partial {
+            let result = metadata.get_info(&info.url).await;
+
+            if let Err(err) = &result {
+                warn!("[hydration] Dropping {}: {}", info.url, err);
+            }
+
+            dropped.append(
+                &mut shared_media_queue_lock
+                    .lock()
+                    .await
+                    .hydrate(&info.url, result),
+            );
+            // the player may be waiting for this entry
+            shared_media_queue_condvar.notify_one();
+        }
+
+        if !dropped.is_empty() {
+            self.message_ctx
+                .send_error(format!(
+                    "Removed songs that couldn't be loaded:\n{}",
+                    dropped
+                        .iter()
+                        .map(|title| format!("**{}**", strings::escape_string(title)))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                ))
+                .await;
+        }
+
+        true
     }
 
     async fn media_player_run(
@@ -459,8 +1879,12 @@ impl ChannelMediaPlayer {
                 // lock and wait for song queue to not be empty
                 let mut shared_media_queue = shared_media_queue_lock.lock().await;
                 while shared_media_queue.queue.is_empty() {
+                    shared_media_queue
+                        .idle_since
+                        .get_or_insert_with(Instant::now);
                     shared_media_queue = shared_media_queue_condvar.wait(shared_media_queue).await;
                 }
+                shared_media_queue.idle_since = None;
                 let next_song = shared_media_queue.queue.pop_back().unwrap();
 
                 if !shared_media_queue.running_state || next_song.is_none() {
@@ -470,8 +1894,36 @@ impl ChannelMediaPlayer {
                 // get song from queue and create source, track, trackhandle
                 // set current song
                 let next_song = next_song.unwrap();
+
+                // flat playlist entries can't be played until they are looked up, which may
+                // not have happened yet. they are dropped with a notice if the lookup fails.
+                // the lookup is left to the hydration task so an entry is never looked up twice.
+                if next_song.info.partial {
+                    shared_media_queue.queue.push_back(Some(next_song));
+                    drop(shared_media_queue);
+
+                    shared_channel_media_player.signal_hydration().await;
+
+                    let mut shared_media_queue = shared_media_queue_lock.lock().await;
+                    while shared_media_queue.running_state
+                        && matches!(shared_media_queue.queue.back(), Some(Some(item)) if item.info.partial)
+                    {
+                        shared_media_queue =
+                            shared_media_queue_condvar.wait(shared_media_queue).await;
+                    }
+
+                    continue 'medialoop;
+                }
+
                 let message_ctx = next_song.message_ctx.clone();
-                let source = match Restartable::ytdl(next_song.info.url.clone(), false).await {
+                let start_at = shared_media_queue.restart_at.take();
+                let source = match audio_source::create(
+                    &next_song.info,
+                    shared_media_queue.audio_filters(),
+                    start_at.is_some(),
+                )
+                .await
+                {
                     Ok(source) => source,
                     Err(why) => {
                         error!("Error creating source: {:?}", why);
@@ -483,8 +1935,22 @@ impl ChannelMediaPlayer {
                         continue 'medialoop;
                     }
                 };
-                let (track, track_handle) = songbird::create_player(Input::from(source));
+                let live = next_song.info.live;
+                let (mut track, track_handle) = songbird::create_player(Input::from(source));
+                track.set_volume(shared_media_queue.volume);
                 shared_media_queue.now_playing = Some((next_song, track_handle.clone()));
+                shared_media_queue.seeked_to = start_at.unwrap_or_default();
+                // a restarted song stays paused
+                if start_at.is_some() && shared_media_queue.paused_at.is_some() {
+                    track.pause();
+                } else {
+                    shared_media_queue.paused_at = None;
+                }
+                if start_at.is_none() {
+                    shared_media_queue.track_number += 1;
+                    shared_media_queue.skip_votes.clear();
+                }
+                shared_channel_media_player.signal_hydration().await;
 
                 // create a condvar to signal the end of the song
                 // give the condvar to media event handler
@@ -513,6 +1979,14 @@ impl ChannelMediaPlayer {
                 let mut vc_handler = voice_channel_handler.lock().await;
                 vc_handler.play(track);
 
+                // the source is lazy in this case, so seeking starts it at the position.
+                // live streams are rejoined where they are now instead.
+                if let Some(start_at) = start_at.filter(|_| !live) {
+                    if let Err(err) = track_handle.seek_time(start_at) {
+                        error!("Error seeking restarted track: {:?}", err);
+                    }
+                }
+
                 shared_media_queue.running_state
             };
 
@@ -527,10 +2001,24 @@ impl ChannelMediaPlayer {
 
             {
                 let mut shared_media_queue = shared_media_queue_lock.lock().await;
-                shared_media_queue.now_playing = None;
+                let finished = shared_media_queue.now_playing.take();
                 if !shared_media_queue.running_state {
                     break 'medialoop;
                 }
+
+                // the source is recreated from the url when the item is popped again
+                if let Some((media_item, _)) = finished {
+                    let info = media_item.info.clone();
+                    let played_through = shared_media_queue.finish_song(
+                        media_item,
+                        config::queue::played_size(shared_channel_media_player.guild_id),
+                    );
+
+                    // skipped songs are not worth keeping, and streams never finish
+                    if played_through && info.source == MediaSource::YoutubeDL && !info.live {
+                        audio_cache::store(&info);
+                    }
+                }
             }
         }
 
@@ -540,3 +2028,592 @@ impl ChannelMediaPlayer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::{
+        fake_metadata::{
+            mock_media_player, mock_message_ctx, mock_requester, FakeMetadataProvider, GUILD_ID,
+            MIXCLOUD_ENTRY_URL, MIXCLOUD_PLAYLIST_URL, MIXCLOUD_URL, VIDEO_URL,
+        },
+        media_info::PlaylistInfo,
+        metadata::PlaylistItems,
+    };
+    use poise::serenity_prelude::{ChannelId, Http, UserId};
+
+    fn mock_queue(titles: &[&str]) -> MediaQueue {
+        let mut media_queue = MediaQueue {
+            running_state: true,
+            now_playing: None,
+            queue: LinkedList::new(),
+            shuffle_mode: false,
+            fair_mode: false,
+            loop_mode: LoopMode::Off,
+            skip_requested: false,
+            skip_votes: HashSet::new(),
+            track_number: 0,
+            paused_at: None,
+            volume: 1.0,
+            normalize: false,
+            filters: vec![],
+            restart_at: None,
+            seeked_to: Duration::ZERO,
+            played: LinkedList::new(),
+            idle_since: None,
+        };
+
+        for title in titles {
+            media_queue.push(MediaItem {
+                info: MediaInfo {
+                    title: title.to_string(),
+                    ..MediaInfo::empty()
+                },
+                message_ctx: mock_message_ctx(),
+                requester: mock_requester(),
+            });
+        }
+
+        media_queue
+    }
+
+    /// Titles in play order, i.e. starting from queue position 2
+    fn titles(media_queue: &MediaQueue) -> Vec<String> {
+        media_queue
+            .queue
+            .iter()
+            .rev()
+            .map(|item| item.as_ref().unwrap().info.title.clone())
+            .collect()
+    }
+
+    mod queue_index {
+        use super::super::queue_index;
+
+        #[test]
+        fn first_in_queue() {
+            assert_eq!(queue_index(2, 3), Ok(2));
+        }
+
+        #[test]
+        fn last_in_queue() {
+            assert_eq!(queue_index(4, 3), Ok(0));
+        }
+
+        #[test]
+        fn now_playing() {
+            assert!(queue_index(1, 3).is_err());
+        }
+
+        #[test]
+        fn out_of_range() {
+            assert!(queue_index(0, 3).is_err());
+            assert!(queue_index(5, 3).is_err());
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let mut media_queue = mock_queue(&["a", "b", "c"]);
+
+        let removed = media_queue.remove(3).unwrap();
+
+        assert_eq!(removed.info.title, "b");
+        assert_eq!(titles(&media_queue), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn remove_out_of_range() {
+        let mut media_queue = mock_queue(&["a", "b", "c"]);
+
+        assert!(media_queue.remove(5).is_err());
+        assert_eq!(titles(&media_queue), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn move_item_forward() {
+        let mut media_queue = mock_queue(&["a", "b", "c", "d"]);
+
+        let moved = media_queue.move_item(5, 2).unwrap();
+
+        assert_eq!(moved.title, "d");
+        assert_eq!(titles(&media_queue), vec!["d", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn move_item_backward() {
+        let mut media_queue = mock_queue(&["a", "b", "c", "d"]);
+
+        media_queue.move_item(2, 4).unwrap();
+
+        assert_eq!(titles(&media_queue), vec!["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn move_item_out_of_range() {
+        let mut media_queue = mock_queue(&["a", "b"]);
+
+        assert!(media_queue.move_item(2, 4).is_err());
+        assert_eq!(titles(&media_queue), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn audio_filters_normalize_last() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.normalize = true;
+        media_queue.filters = vec![AudioFilter::BassBoost, AudioFilter::EightD];
+
+        assert_eq!(
+            media_queue.audio_filters(),
+            vec![
+                AudioFilter::BassBoost.ffmpeg_filter(),
+                AudioFilter::EightD.ffmpeg_filter(),
+                LOUDNORM_FILTER
+            ]
+        );
+    }
+
+    #[test]
+    fn song_time_with_filters() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.filters = vec![AudioFilter::Nightcore];
+
+        assert_eq!(
+            media_queue.song_time(Duration::from_secs(40)),
+            Duration::from_secs(50)
+        );
+
+        // restarted at 50s, then played 8s with nightcore
+        media_queue.seeked_to = Duration::from_secs(50);
+        assert_eq!(
+            media_queue.song_time(Duration::from_secs(58)),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn speed_of_filters() {
+        let mut media_queue = mock_queue(&[]);
+        assert_eq!(media_queue.speed(), 1.0);
+
+        media_queue.filters = vec![AudioFilter::BassBoost, AudioFilter::Nightcore];
+        assert_eq!(media_queue.speed(), 1.25);
+
+        media_queue.filters = vec![AudioFilter::Nightcore, AudioFilter::Vaporwave];
+        assert_eq!(media_queue.speed(), 1.0);
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut media_queue = mock_queue(&["queued"]);
+        let (_, queue) = mock_queue(&["a", "b", "c"]).upcoming();
+        let snapshot = QueueSnapshot {
+            guild_id: GuildId(1),
+            voice_channel: ChannelId(2),
+            text_channel: ChannelId(3),
+            now_playing: Some(SnapshotSong {
+                info: MediaInfo {
+                    title: "current".to_string(),
+                    ..MediaInfo::empty()
+                },
+                requester: mock_requester(),
+            }),
+            position: 30,
+            queue,
+        };
+
+        let message_ctx = MessageContext {
+            channel: ChannelId(3),
+            http: Arc::new(Http::new("")),
+        };
+
+        media_queue.restore(&snapshot, message_ctx);
+
+        assert_eq!(
+            titles(&media_queue),
+            vec!["current", "a", "b", "c", "queued"]
+        );
+        assert_eq!(media_queue.restart_at, Some(Duration::from_secs(30)));
+    }
+
+    fn mock_item(title: &str, user_id: u64) -> MediaItem {
+        MediaItem {
+            info: MediaInfo {
+                title: title.to_string(),
+                ..MediaInfo::empty()
+            },
+            message_ctx: mock_message_ctx(),
+            requester: Requester {
+                user_id: UserId(user_id),
+                name: user_id.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn fair_mode_takes_turns() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.fair_mode = true;
+
+        media_queue.push(mock_item("a1", 1));
+        media_queue.push(mock_item("a2", 1));
+        media_queue.push(mock_item("a3", 1));
+        media_queue.push(mock_item("b1", 2));
+        media_queue.push(mock_item("c1", 3));
+        media_queue.push(mock_item("b2", 2));
+
+        assert_eq!(
+            titles(&media_queue),
+            vec!["a1", "b1", "c1", "a2", "b2", "a3"]
+        );
+    }
+
+    #[test]
+    fn fair_mode_rearranges_queue() {
+        let mut media_queue = mock_queue(&[]);
+
+        media_queue.push(mock_item("a1", 1));
+        media_queue.push(mock_item("a2", 1));
+        media_queue.push(mock_item("b1", 2));
+        media_queue.push(mock_item("b2", 2));
+
+        media_queue.arrange_fair();
+
+        assert_eq!(titles(&media_queue), vec!["a1", "b1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn fair_mode_counts_playing_song() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.fair_mode = true;
+
+        let (_, track_handle) = songbird::tracks::create_player(Input::float_pcm(
+            false,
+            songbird::input::Reader::from_memory(vec![]),
+        ));
+        media_queue.now_playing = Some((mock_item("a1", 1), track_handle));
+
+        media_queue.push(mock_item("a2", 1));
+        media_queue.push(mock_item("b1", 2));
+
+        assert_eq!(titles(&media_queue), vec!["b1", "a2"]);
+    }
+
+    #[test]
+    fn fair_mode_survives_shuffle() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.fair_mode = true;
+
+        media_queue.push(mock_item("a1", 1));
+        media_queue.push(mock_item("a2", 1));
+        media_queue.push(mock_item("b1", 2));
+
+        media_queue.shuffle();
+
+        let requesters: Vec<u64> = media_queue
+            .queue
+            .iter()
+            .rev()
+            .flatten()
+            .map(|item| item.requester.user_id.0)
+            .collect();
+        assert_ne!(requesters[0], requesters[1]);
+    }
+
+    #[test]
+    fn fair_mode_takes_turns_with_looped_songs() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.fair_mode = true;
+        media_queue.loop_mode = LoopMode::Queue;
+
+        media_queue.push(mock_item("a1", 1));
+        media_queue.push(mock_item("a2", 1));
+        media_queue.push(mock_item("a3", 1));
+
+        media_queue.requeue_finished(mock_item("b1", 2), false);
+
+        assert_eq!(titles(&media_queue), vec!["a1", "b1", "a2", "a3"]);
+    }
+
+    #[test]
+    fn restart_replays_song() {
+        let mut media_queue = mock_queue(&["next"]);
+        media_queue.restart_at = Some(Duration::from_secs(30));
+
+        assert!(!media_queue.finish_song(mock_item("current", 1), 10));
+
+        assert_eq!(titles(&media_queue), vec!["current", "next"]);
+        assert_eq!(media_queue.restart_at, Some(Duration::from_secs(30)));
+        assert!(media_queue.played.is_empty());
+    }
+
+    #[test]
+    fn skip_during_restart() {
+        let mut media_queue = mock_queue(&["next"]);
+        media_queue.loop_mode = LoopMode::Track;
+        media_queue.restart_at = Some(Duration::from_secs(30));
+        media_queue.skip_requested = true;
+
+        assert!(!media_queue.finish_song(mock_item("current", 1), 10));
+
+        assert_eq!(titles(&media_queue), vec!["next"]);
+        assert_eq!(media_queue.restart_at, None);
+        assert!(!media_queue.skip_requested);
+        assert_eq!(media_queue.played.len(), 1);
+
+        // the next song loops and counts as played through again
+        assert!(media_queue.finish_song(mock_item("next", 1), 10));
+        assert_eq!(titles(&media_queue), vec!["next", "next"]);
+    }
+
+    #[test]
+    fn duration_leaves_out_live_streams() {
+        let mut media_queue = mock_queue(&[]);
+
+        for (duration, live) in [(60, false), (0, true), (125, false)] {
+            let mut item = mock_item("song", 1);
+            item.info.duration = duration;
+            item.info.live = live;
+            media_queue.push(item);
+        }
+
+        assert_eq!(media_queue.duration(), (185, 1));
+    }
+
+    #[test]
+    fn skip_votes_are_counted_once() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.track_number = 3;
+
+        assert_eq!(media_queue.add_skip_vote(UserId(1), None), Ok(1));
+        assert_eq!(media_queue.add_skip_vote(UserId(1), Some(3)), Ok(1));
+        assert_eq!(media_queue.add_skip_vote(UserId(2), Some(3)), Ok(2));
+    }
+
+    #[test]
+    fn skip_votes_for_ended_song() {
+        let mut media_queue = mock_queue(&[]);
+        media_queue.track_number = 3;
+
+        assert!(media_queue.add_skip_vote(UserId(1), Some(2)).is_err());
+        assert!(media_queue.skip_votes.is_empty());
+    }
+
+    #[test]
+    fn played_is_bounded() {
+        let mut media_queue = mock_queue(&["a", "b", "c"]);
+
+        while let Some(Some(item)) = media_queue.queue.pop_back() {
+            media_queue.push_played(item, 2);
+        }
+
+        let played = media_queue
+            .played
+            .iter()
+            .map(|item| item.info.title.clone())
+            .collect::<Vec<String>>();
+
+        assert_eq!(played, vec!["c", "b"]);
+    }
+
+    #[test]
+    fn swap() {
+        let mut media_queue = mock_queue(&["a", "b", "c", "d"]);
+
+        let (a, b) = media_queue.swap(2, 4).unwrap();
+
+        assert_eq!(a.title, "c");
+        assert_eq!(b.title, "a");
+        assert_eq!(titles(&media_queue), vec!["c", "b", "a", "d"]);
+    }
+
+    /// Turns the songs into flat playlist entries, with the title as url
+    fn make_partial(media_queue: &mut MediaQueue) {
+        for item in media_queue.queue.iter_mut().flatten() {
+            item.info.url = item.info.title.clone();
+            item.info.partial = true;
+        }
+    }
+
+    #[test]
+    fn partial_ahead_only_looks_ahead() {
+        let mut media_queue = mock_queue(&["a", "b", "c"]);
+        make_partial(&mut media_queue);
+
+        let partial = media_queue.partial_ahead(2);
+
+        assert_eq!(
+            partial.iter().map(|info| &info.title).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn hydrate_replaces_entry() {
+        let mut media_queue = mock_queue(&["a", "b"]);
+        make_partial(&mut media_queue);
+        media_queue
+            .queue
+            .back_mut()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .info
+            .playlist = Some(PlaylistInfo {
+            title: "playlist".to_string(),
+            uploader: "".to_string(),
+        });
+
+        let dropped = media_queue.hydrate(
+            "a",
+            Ok(MediaInfo {
+                url: "a".to_string(),
+                title: "full a".to_string(),
+                thumbnail: "thumbnail".to_string(),
+                ..MediaInfo::empty()
+            }),
+        );
+
+        let info = &media_queue.queue.back().unwrap().as_ref().unwrap().info;
+
+        assert!(dropped.is_empty());
+        assert_eq!(titles(&media_queue), vec!["full a", "b"]);
+        assert!(!info.partial);
+        assert_eq!(info.playlist.as_ref().unwrap().title, "playlist");
+    }
+
+    #[test]
+    fn hydrate_removes_unresolved() {
+        let mut media_queue = mock_queue(&["a", "b", "c"]);
+        make_partial(&mut media_queue);
+
+        let dropped = media_queue.hydrate("b", Err("Unable to parse json".to_string()));
+
+        assert_eq!(dropped, vec!["b"]);
+        assert_eq!(titles(&media_queue), vec!["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn hydrate_next_looks_up_entries() {
+        let media_player = ChannelMediaPlayer::create(GUILD_ID, mock_message_ctx());
+        let metadata = FakeMetadataProvider::new();
+
+        media_player
+            .enqueue_batch(
+                LinkedList::from([MediaInfo {
+                    url: VIDEO_URL.to_string(),
+                    title: "flat".to_string(),
+                    partial: true,
+                    ..MediaInfo::empty()
+                }]),
+                media_player.message_ctx.clone(),
+                mock_requester(),
+            )
+            .await;
+
+        assert!(media_player.hydrate_next(&metadata).await);
+        assert!(!media_player.hydrate_next(&metadata).await);
+
+        let (queue, _) = media_player.read_queue(1, 1).await;
+        let (info, _) = queue.front().unwrap();
+
+        assert_eq!(
+            info.title,
+            "Rick Astley - Never Gonna Give You Up (Official Music Video)"
+        );
+        assert!(!info.partial);
+    }
+
+    #[tokio::test]
+    async fn hydrate_next_looks_up_single_format_entries() {
+        let media_player = ChannelMediaPlayer::create(GUILD_ID, mock_message_ctx());
+        let metadata = FakeMetadataProvider::new();
+
+        media_player
+            .enqueue_batch(
+                LinkedList::from([MediaInfo {
+                    url: MIXCLOUD_URL.to_string(),
+                    title: "flat".to_string(),
+                    partial: true,
+                    ..MediaInfo::empty()
+                }]),
+                media_player.message_ctx.clone(),
+                mock_requester(),
+            )
+            .await;
+
+        assert!(media_player.hydrate_next(&metadata).await);
+
+        let (queue, _) = media_player.read_queue(1, 1).await;
+        let (info, _) = queue.front().unwrap();
+
+        assert_eq!(info.title, "Floating Points - 8th October 2019");
+        assert!(!info.partial);
+    }
+
+    #[tokio::test]
+    async fn hydrate_next_keeps_playlist_entries_of_other_hosts() {
+        let media_player = ChannelMediaPlayer::create(GUILD_ID, mock_message_ctx());
+        let metadata = FakeMetadataProvider::new();
+        let entries = metadata
+            .get_playlist(
+                MIXCLOUD_PLAYLIST_URL,
+                Some(&PlaylistItems::Ranges(vec![(2, 2)])),
+            )
+            .await
+            .unwrap();
+        assert!(entries.front().unwrap().partial);
+
+        media_player
+            .enqueue_batch(entries, media_player.message_ctx.clone(), mock_requester())
+            .await;
+
+        assert!(media_player.hydrate_next(&metadata).await);
+
+        let (queue, _) = media_player.read_queue(1, 10).await;
+        let (info, _) = queue.front().unwrap();
+
+        assert_eq!(queue.len(), 1);
+        assert_eq!(info.url, MIXCLOUD_ENTRY_URL);
+        assert!(!info.partial);
+        assert_eq!(info.playlist.as_ref().unwrap().title, "Ambient");
+    }
+
+    #[tokio::test]
+    async fn audio_settings_outlast_the_player() {
+        let media_player = mock_media_player().await;
+
+        media_player.set_volume(GUILD_ID, 0.5).await.unwrap();
+        media_player
+            .set_normalize(GUILD_ID, Some(true))
+            .await
+            .unwrap();
+        media_player
+            .toggle_filter(GUILD_ID, Some(AudioFilter::Nightcore))
+            .await
+            .unwrap();
+
+        media_player.quit(GUILD_ID).await.unwrap();
+        media_player
+            .start_without_voice(GUILD_ID, mock_message_ctx())
+            .await
+            .unwrap();
+
+        let channel_media_player = media_player
+            .guild_media_player_map
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()[&GUILD_ID]
+            .clone();
+
+        assert_eq!(
+            channel_media_player.audio_settings().await,
+            AudioSettings {
+                volume: 0.5,
+                normalize: true,
+                filters: vec![AudioFilter::Nightcore],
+            }
+        );
+    }
+}
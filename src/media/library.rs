@@ -0,0 +1,190 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config;
+
+use super::{
+    media_info::{MediaInfo, MediaSource},
+    probe,
+};
+
+/// Prefix of queries and urls of songs in the music library
+pub const LOCAL_PREFIX: &str = "local:";
+
+/// Path or search query of a `local:` query or url
+pub fn parse_local(query: &str) -> Option<&str> {
+    query
+        .strip_prefix(LOCAL_PREFIX)
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+}
+
+pub fn local_url(path: &str) -> String {
+    format!("{}{}", LOCAL_PREFIX, path)
+}
+
+/// Audio files in the library, as sorted paths relative to it. Symlinked directories are
+/// skipped so that loops can't be followed.
+pub fn files(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().map_or(false, |extension| {
+                probe::AUDIO_EXTENSIONS
+                    .contains(&extension.to_string_lossy().to_lowercase().as_str())
+            }) {
+                if let Ok(relative) = path.strip_prefix(dir) {
+                    files.push(relative.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Same as `files`, but walks the library on a blocking thread so that a large library doesn't
+/// hold up the runtime
+pub async fn list(dir: PathBuf) -> io::Result<Vec<String>> {
+    tokio::task::spawn_blocking(move || files(&dir))
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+}
+
+/// Files whose path contains every word of the query, ignoring case
+pub fn search<'a>(files: &'a [String], query: &str) -> Vec<&'a String> {
+    let query = query.to_lowercase();
+    let words: Vec<&str> = query.split_whitespace().collect();
+
+    files
+        .iter()
+        .filter(|file| {
+            let file = file.to_lowercase();
+            words.iter().all(|word| file.contains(word))
+        })
+        .collect()
+}
+
+/// Absolute path of a file in the library. Paths that lead out of the library are rejected.
+pub fn resolve(dir: &Path, path: &str) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    let path = dir.join(path).canonicalize().ok()?;
+
+    (path.starts_with(&dir) && path.is_file()).then_some(path)
+}
+
+/// Absolute path of the file a `local:` url points to
+pub fn path_of(url: &str) -> Option<PathBuf> {
+    resolve(&config::library::dir()?, parse_local(url)?)
+}
+
+/// Looks up a song by its path in the library, or else the first file matching the query
+pub async fn get_info(query: &str) -> Result<MediaInfo, String> {
+    let Some(dir) = config::library::dir() else {
+        return Err("The music library is disabled!".to_string());
+    };
+
+    let path = match resolve(&dir, query) {
+        Some(_) => query.to_string(),
+        None => {
+            let files = list(dir.clone()).await.map_err(|err| err.to_string())?;
+
+            match search(&files, query).first() {
+                Some(file) => file.to_string(),
+                None => return Err("No songs found in the library!".to_string()),
+            }
+        }
+    };
+
+    match resolve(&dir, &path) {
+        Some(file) => probe::get_info(file, &local_url(&path), MediaSource::Library).await,
+        None => Err("No songs found in the library!".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_library(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mimicri-{}-{}", name, std::process::id()));
+
+        fs::create_dir_all(dir.join("Album")).unwrap();
+        fs::write(dir.join("Album/01 Intro.flac"), []).unwrap();
+        fs::write(dir.join("Album/02 Outro.MP3"), []).unwrap();
+        fs::write(dir.join("Album/cover.jpg"), []).unwrap();
+        fs::write(dir.join("single.ogg"), []).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn local_prefix() {
+        assert_eq!(
+            parse_local("local:Album/01 Intro.flac"),
+            Some("Album/01 Intro.flac")
+        );
+        assert_eq!(parse_local("local: intro "), Some("intro"));
+        assert_eq!(parse_local("local:"), None);
+        assert_eq!(parse_local("intro"), None);
+        assert_eq!(local_url("single.ogg"), "local:single.ogg");
+    }
+
+    #[test]
+    fn lists_audio_files() {
+        let dir = mock_library("library-files");
+
+        assert_eq!(
+            files(&dir).unwrap(),
+            vec!["Album/01 Intro.flac", "Album/02 Outro.MP3", "single.ogg"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lists_on_blocking_thread() {
+        let dir = mock_library("library-list");
+
+        assert_eq!(list(dir.clone()).await.unwrap(), files(&dir).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn searches_every_word() {
+        let files = vec![
+            "Album/01 Intro.flac".to_string(),
+            "Album/02 Outro.MP3".to_string(),
+            "single.ogg".to_string(),
+        ];
+
+        assert_eq!(search(&files, "album outro"), vec!["Album/02 Outro.MP3"]);
+        assert_eq!(search(&files, "album").len(), 2);
+        assert!(search(&files, "album single").is_empty());
+    }
+
+    #[test]
+    fn resolves_inside_library() {
+        let dir = mock_library("library-resolve");
+
+        assert!(resolve(&dir, "single.ogg").is_some());
+        assert!(resolve(&dir, "Album/../single.ogg").is_some());
+        assert!(resolve(&dir, "Album").is_none());
+        assert!(resolve(&dir, "missing.ogg").is_none());
+        assert!(resolve(&dir.join("Album"), "../single.ogg").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
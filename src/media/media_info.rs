@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use serenity::model::prelude::UserId;
+
+use crate::Context;
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
 pub struct MediaInfo {
@@ -22,6 +25,29 @@ pub struct MediaInfo {
 
     #[serde(default)]
     pub playlist: Option<PlaylistInfo>,
+
+    /// Only has what a flat playlist listing provides, until the full metadata is looked up
+    #[serde(default)]
+    pub partial: bool,
+
+    #[serde(default)]
+    pub source: MediaSource,
+
+    /// Live streams have no duration and can't be seeked
+    #[serde(default)]
+    pub live: bool,
+}
+
+/// Where the audio of a song is read from
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, Default)]
+pub enum MediaSource {
+    /// Resolved by youtube-dl
+    #[default]
+    YoutubeDL,
+    /// A file in the music library, with a `local:` url
+    Library,
+    /// Read by ffmpeg from the url, like audio files, radio streams and attachments
+    Direct,
 }
 
 impl MediaInfo {
@@ -34,6 +60,39 @@ impl MediaInfo {
             thumbnail: "".to_string(),
             uploader: "".to_string(),
             playlist: None,
+            partial: false,
+            source: MediaSource::YoutubeDL,
+            live: false,
+        }
+    }
+
+    /// Web page of the song, if it has one. Library files don't.
+    pub fn link(&self) -> Option<&String> {
+        match self.source {
+            MediaSource::Library => None,
+            _ => Some(&self.url),
+        }
+    }
+}
+
+/// The user who queued a song
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Requester {
+    pub user_id: UserId,
+    /// Display name at the time the song was queued
+    pub name: String,
+}
+
+impl Requester {
+    pub async fn from_author(ctx: Context<'_>) -> Requester {
+        let name = match ctx.author_member().await {
+            Some(member) => member.display_name().into_owned(),
+            None => ctx.author().name.clone(),
+        };
+
+        Requester {
+            user_id: ctx.author().id,
+            name,
         }
     }
 }
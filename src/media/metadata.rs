@@ -1,15 +1,22 @@
+use poise::async_trait;
 use serde::{Deserialize, Serialize};
-use std::{collections::LinkedList, process};
-use tracing::error;
+use std::{collections::LinkedList, fmt::Display, process::Output, time::Duration};
+use tokio::process;
+use tracing::{error, warn};
 
-use super::media_info::{MediaInfo, PlaylistInfo};
+use crate::{config, utils::strings};
 
-const YOUTUBE_DL_COMMAND: &str = "yt-dlp";
+use super::media_info::{MediaInfo, MediaSource, PlaylistInfo};
+
+pub const YOUTUBE_DL_COMMAND: &str = "yt-dlp";
 
 #[derive(Serialize, Deserialize)]
 struct YoutubeDLJson {
     id: Option<String>,
+    /// Stream of the format that was picked, unless formats are merged
     url: Option<String>,
+    /// Formats that are merged, each with its own stream url
+    requested_formats: Option<Vec<serde_json::Value>>,
     title: Option<String>,
     description: Option<String>,
     duration: Option<f64>,
@@ -17,6 +24,7 @@ struct YoutubeDLJson {
     webpage_url: Option<String>,
     uploader: Option<String>,
     channel: Option<String>,
+    is_live: Option<bool>,
     // playlist
     playlist_title: Option<String>,
     playlist_uploader: Option<String>,
@@ -27,10 +35,25 @@ struct YoutubeDLJson {
 struct YoutubeDLFlatJson {
     ie_key: Option<String>,
     id: Option<String>,
+    url: Option<String>,
     title: Option<String>,
     description: Option<String>,
     duration: Option<f64>,
     uploader: Option<String>,
+    channel: Option<String>,
+    live_status: Option<String>,
+    thumbnails: Option<Vec<YoutubeDLThumbnailJson>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct YoutubeDLThumbnailJson {
+    url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct YoutubeDLTypeJson {
+    #[serde(rename = "_type")]
+    kind: Option<String>,
 }
 
 impl From<YoutubeDLJson> for MediaInfo {
@@ -49,6 +72,9 @@ impl From<YoutubeDLJson> for MediaInfo {
                 }),
                 None => None,
             },
+            partial: false,
+            source: MediaSource::YoutubeDL,
+            live: json.is_live.unwrap_or_default(),
         }
     }
 }
@@ -60,7 +86,7 @@ impl From<YoutubeDLFlatJson> for MediaInfo {
         let url = if platform == "Youtube" {
             "https://www.youtube.com/watch?v=".to_string() + &json.id.unwrap_or_default()
         } else {
-            "".to_string()
+            json.url.unwrap_or_default()
         };
 
         MediaInfo {
@@ -68,201 +94,1017 @@ impl From<YoutubeDLFlatJson> for MediaInfo {
             title: json.title.unwrap_or_default(),
             duration: json.duration.unwrap_or_default() as i64,
             description: json.description.unwrap_or_default(),
-            uploader: json.uploader.unwrap_or_default(),
-            thumbnail: "".to_string(), // FIXME
+            uploader: json.uploader.or(json.channel).unwrap_or_default(),
+            // Thumbnails are sorted from worst to best
+            thumbnail: json
+                .thumbnails
+                .and_then(|thumbnails| thumbnails.into_iter().rev().find_map(|t| t.url))
+                .unwrap_or_default(),
             playlist: None,
+            partial: true,
+            source: MediaSource::YoutubeDL,
+            live: json.live_status.as_deref() == Some("is_live"),
         }
     }
 }
 
-pub fn get_info(url: &String) -> Result<MediaInfo, String> {
-    match process::Command::new(YOUTUBE_DL_COMMAND)
-        .arg("-j")
-        .arg("--no-playlist")
-        .arg(url)
-        .output()
+/// Runs youtube-dl with the given arguments.
+///
+/// The process is killed if it doesn't finish within the timeout, or if the returned future is
+/// dropped.
+async fn run_youtube_dl(args: &[&str], timeout: Duration) -> Result<Output, String> {
+    let child = match process::Command::new(YOUTUBE_DL_COMMAND)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
     {
+        Ok(child) => child,
         Err(_) => return Err("Failed to run youtube-dl".to_string()),
-        Ok(output) => {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let err_str = String::from_utf8_lossy(&output.stderr);
+    };
 
-            if !err_str.is_empty() {
-                error!("[metadata] [youtube-dl] {}", err_str);
-            }
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(_)) => Err("Failed to run youtube-dl".to_string()),
+        Err(_) => {
+            error!("[metadata] [youtube-dl] Timed out: {:?}", args);
+            Err(format!(
+                "Looking up the song took longer than {} seconds",
+                timeout.as_secs()
+            ))
+        }
+    }
+}
 
-            let json_result: serde_json::Result<YoutubeDLJson> = serde_json::from_str(&output_str);
+/// A site that youtube-dl can search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchBackend {
+    YouTube,
+    YouTubeMusic,
+    SoundCloud,
+    Bilibili,
+    NicoNico,
+}
 
-            match json_result {
-                Err(err) => {
-                    error!("[metadata] [youtube-dl] [json parse error] {}", output_str);
-                    Err("Unable to parse json".to_string())
-                }
-                Ok(json) => {
-                    if json.url.is_some() {
-                        return Err("[metadata] [youtube-dl] Json returned no URL".to_string());
-                    }
+impl SearchBackend {
+    pub const ALL: [SearchBackend; 5] = [
+        SearchBackend::YouTube,
+        SearchBackend::YouTubeMusic,
+        SearchBackend::SoundCloud,
+        SearchBackend::Bilibili,
+        SearchBackend::NicoNico,
+    ];
 
-                    Ok(MediaInfo::from(json))
-                }
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchBackend::YouTube => "youtube",
+            SearchBackend::YouTubeMusic => "youtube-music",
+            SearchBackend::SoundCloud => "soundcloud",
+            SearchBackend::Bilibili => "bilibili",
+            SearchBackend::NicoNico => "niconico",
+        }
+    }
+
+    /// Short prefix to pick the backend in a query, e.g. `sc:query`
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            SearchBackend::YouTube => "yt",
+            SearchBackend::YouTubeMusic => "ytm",
+            SearchBackend::SoundCloud => "sc",
+            SearchBackend::Bilibili => "bili",
+            SearchBackend::NicoNico => "nico",
+        }
+    }
+
+    /// Parses a backend from its name or prefix
+    pub fn parse(text: &str) -> Option<SearchBackend> {
+        let text = text.trim().to_lowercase();
+
+        SearchBackend::ALL
+            .into_iter()
+            .find(|backend| backend.name() == text || backend.prefix() == text)
+    }
+
+    /// What to pass youtube-dl to search for the top `count` results
+    pub fn search_term(&self, query: &str, count: usize) -> String {
+        match self {
+            SearchBackend::YouTube => format!("ytsearch{}:{}", count, query),
+            // YouTube Music has no search prefix, but youtube-dl can list its search page
+            SearchBackend::YouTubeMusic => format!(
+                "https://music.youtube.com/search?q={}#songs",
+                url::form_urlencoded::byte_serialize(query.as_bytes()).collect::<String>()
+            ),
+            SearchBackend::SoundCloud => format!("scsearch{}:{}", count, query),
+            SearchBackend::Bilibili => format!("bilisearch{}:{}", count, query),
+            SearchBackend::NicoNico => format!("nicosearch{}:{}", count, query),
+        }
+    }
+}
+
+/// Which songs of a playlist to queue, e.g. `1-20`, `5,7,9` or `last 10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistItems {
+    /// Inclusive ranges of 1-based indices
+    Ranges(Vec<(usize, usize)>),
+    Last(usize),
+}
+
+impl PlaylistItems {
+    pub fn parse(text: &str) -> Result<PlaylistItems, String> {
+        let text = text.trim().to_lowercase();
+        let invalid = || format!("Invalid playlist items: {}", text);
+
+        if let Some(count) = text.strip_prefix("last") {
+            return match count.trim().parse::<usize>() {
+                Ok(count) if count > 0 => Ok(PlaylistItems::Last(count)),
+                _ => Err(invalid()),
+            };
+        }
+
+        let mut ranges = vec![];
+
+        for part in text.split(',') {
+            let (start, end) = part.split_once('-').unwrap_or((part, part));
+
+            match (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+                (Ok(start), Ok(end)) if start > 0 && start <= end => ranges.push((start, end)),
+                _ => return Err(invalid()),
             }
         }
+
+        Ok(PlaylistItems::Ranges(ranges))
+    }
+
+    /// Value of youtube-dl's `--playlist-items`
+    pub fn arg(&self) -> String {
+        match self {
+            PlaylistItems::Ranges(_) => self.to_string().replace(' ', ""),
+            PlaylistItems::Last(count) => format!("-{}:", count),
+        }
+    }
+
+    /// Value of youtube-dl's `--playlist-items` for only the first selected song, given how many
+    /// songs `arg` selected. Playlists shorter than `last n` start at their first song.
+    pub fn first_arg(&self, selected: usize) -> String {
+        match self {
+            PlaylistItems::Ranges(ranges) => ranges[0].0.to_string(),
+            PlaylistItems::Last(count) => format!("-{}", (*count).min(selected).max(1)),
+        }
     }
 }
 
-pub fn get_search(query: &String) -> Result<MediaInfo, String> {
-    get_info(&format!("ytsearch:{}", query))
+impl Display for PlaylistItems {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaylistItems::Ranges(ranges) => write!(
+                f,
+                "{}",
+                ranges
+                    .iter()
+                    .map(|(start, end)| if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{}-{}", start, end)
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            PlaylistItems::Last(count) => write!(f, "last {}", count),
+        }
+    }
 }
 
-pub fn get_playlist(url: &String) -> Result<LinkedList<MediaInfo>, String> {
-    let mut sources: LinkedList<MediaInfo> = LinkedList::new();
+/// Splits the playlist items off a query like `<url> 1-20`
+pub fn parse_playlist_items(query: &str) -> Result<(&str, Option<PlaylistItems>), String> {
+    let query = query.trim();
+
+    match query.split_once(char::is_whitespace) {
+        Some((url, items)) if strings::is_url(url) => Ok((url, Some(PlaylistItems::parse(items)?))),
+        _ => Ok((query, None)),
+    }
+}
+
+/// Splits an explicit backend prefix like `sc:query` off a search query
+pub fn parse_search_prefix(query: &str) -> (Option<SearchBackend>, &str) {
+    match query.split_once(':') {
+        Some((prefix, rest)) if !rest.trim().is_empty() => match SearchBackend::parse(prefix) {
+            Some(backend) => (Some(backend), rest.trim()),
+            None => (None, query),
+        },
+        _ => (None, query),
+    }
+}
+
+/// Parses a comma or space separated list of backends, e.g. `youtube, sc`. Repeats are dropped.
+pub fn parse_search_backends(text: &str) -> Result<Vec<SearchBackend>, String> {
+    let mut backends = vec![];
 
-    match process::Command::new(YOUTUBE_DL_COMMAND)
-        .arg("-j")
-        .arg("--playlist-end=1")
-        .arg(&url)
-        .output()
+    for name in text
+        .split([',', ' '])
+        .filter(|name| !name.trim().is_empty())
     {
-        Err(_) => return Err("Failed to run youtube-dl".to_string()),
-        Ok(output) => {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let err_str = String::from_utf8_lossy(&output.stderr);
+        let backend = SearchBackend::parse(name).ok_or_else(|| {
+            format!(
+                "Unknown search backend {}, pick from {}",
+                name.trim(),
+                SearchBackend::ALL.map(|backend| backend.name()).join(", ")
+            )
+        })?;
 
-            if !err_str.is_empty() {
-                error!("[playlist] {}", err_str);
-            }
+        if !backends.contains(&backend) {
+            backends.push(backend);
+        }
+    }
+
+    Ok(backends)
+}
 
-            if let Some(line) = output_str.split("\n").nth(0) {
-                let json_result: serde_json::Result<YoutubeDLJson> = serde_json::from_str(line);
+/// Searches the backends in order, until one of them finds a song.
+///
+/// An explicit prefix in the query only searches that backend.
+pub async fn search(
+    provider: &dyn MetadataProvider,
+    query: &str,
+    backends: &[SearchBackend],
+) -> Result<MediaInfo, String> {
+    let (prefixed, query) = parse_search_prefix(query);
+    let backends = prefixed.map_or(backends.to_vec(), |backend| vec![backend]);
 
-                match json_result {
-                    Ok(json) => sources.push_back(MediaInfo::from(json)),
-                    Err(err) => error!("[playlist] {}", err),
-                }
-            } else {
-                error!("[playlist] First song is empty");
+    let mut last_err = "No results found!".to_string();
+
+    for backend in backends {
+        match provider.get_search(query, backend).await {
+            Ok(info) => return Ok(info),
+            Err(err) => {
+                warn!("[search] {} failed: {}", backend.name(), err);
+                last_err = err;
             }
         }
     }
 
-    match process::Command::new(YOUTUBE_DL_COMMAND)
-        .arg("-j")
-        .arg("--flat-playlist")
-        .arg(&url)
-        .output()
-    {
-        Err(_) => return Err("Failed to run youtube-dl".to_string()),
-        Ok(output) => {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let err_str = String::from_utf8_lossy(&output.stderr);
+    Err(last_err)
+}
+
+/// Like `search`, but returns the top `count` results of the first backend that finds any
+pub async fn search_results(
+    provider: &dyn MetadataProvider,
+    query: &str,
+    count: usize,
+    backends: &[SearchBackend],
+) -> Result<Vec<MediaInfo>, String> {
+    let (prefixed, query) = parse_search_prefix(query);
+    let backends = prefixed.map_or(backends.to_vec(), |backend| vec![backend]);
 
-            if !err_str.is_empty() {
-                error!("[playlist] {}", err_str);
+    let mut last_err = None;
+
+    for backend in backends {
+        match provider.get_search_results(query, backend, count).await {
+            Ok(results) if !results.is_empty() => return Ok(results),
+            Ok(_) => (),
+            Err(err) => {
+                warn!("[search] {} failed: {}", backend.name(), err);
+                last_err = Some(err);
             }
+        }
+    }
 
-            let mut lines = output_str.split("\n");
+    last_err.map_or(Ok(vec![]), Err)
+}
 
-            lines.next();
+#[async_trait]
+pub trait MetadataProvider: Sync + Send {
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String>;
 
-            for line in lines {
-                if line.is_empty() {
-                    continue;
-                }
+    /// Returns the top search result of a backend, with full metadata
+    async fn get_search(&self, query: &str, backend: SearchBackend) -> Result<MediaInfo, String>;
 
-                let json_result: serde_json::Result<YoutubeDLFlatJson> = serde_json::from_str(line);
+    /// Returns the top `count` search results of a backend. Only the title, uploader and
+    /// duration are guaranteed.
+    async fn get_search_results(
+        &self,
+        query: &str,
+        backend: SearchBackend,
+        count: usize,
+    ) -> Result<Vec<MediaInfo>, String>;
 
-                match json_result {
-                    Ok(json) => sources.push_back(MediaInfo::from(json)),
-                    Err(err) => error!("[playlist] {}", err),
+    /// Asks youtube-dl whether the url is a playlist
+    async fn is_playlist(&self, url: &str) -> Result<bool, String>;
+
+    /// Returns the songs of a playlist, or only the selected items. Only the first song has full
+    /// metadata.
+    async fn get_playlist(
+        &self,
+        url: &str,
+        items: Option<&PlaylistItems>,
+    ) -> Result<LinkedList<MediaInfo>, String>;
+}
+
+/// Looks up metadata by running youtube-dl
+pub struct YoutubeDLProvider;
+
+#[async_trait]
+impl MetadataProvider for YoutubeDLProvider {
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String> {
+        match run_youtube_dl(&["-j", "--no-playlist", url], config::timeouts::metadata()).await {
+            Err(err) => Err(err),
+            Ok(output) => {
+                let err_str = String::from_utf8_lossy(&output.stderr);
+
+                if !err_str.is_empty() {
+                    error!("[metadata] [youtube-dl] {}", err_str);
                 }
+
+                parse_info(&String::from_utf8_lossy(&output.stdout))
             }
+        }
+    }
+
+    async fn get_search(&self, query: &str, backend: SearchBackend) -> Result<MediaInfo, String> {
+        let output = run_youtube_dl(
+            &[
+                "-j",
+                "--playlist-items",
+                "1",
+                &backend.search_term(query, 1),
+            ],
+            config::timeouts::metadata(),
+        )
+        .await?;
+
+        let err_str = String::from_utf8_lossy(&output.stderr);
+        if !err_str.is_empty() {
+            error!("[search] {}", err_str);
+        }
+
+        match String::from_utf8_lossy(&output.stdout).lines().next() {
+            Some(line) => parse_info(line),
+            None => Err("No results found!".to_string()),
+        }
+    }
+
+    async fn get_search_results(
+        &self,
+        query: &str,
+        backend: SearchBackend,
+        count: usize,
+    ) -> Result<Vec<MediaInfo>, String> {
+        let output = run_youtube_dl(
+            &[
+                "-j",
+                "--flat-playlist",
+                "--playlist-items",
+                &format!("1:{}", count),
+                &backend.search_term(query, count),
+            ],
+            config::timeouts::metadata(),
+        )
+        .await?;
+
+        let err_str = String::from_utf8_lossy(&output.stderr);
+        if !err_str.is_empty() {
+            error!("[search] {}", err_str);
+        }
+
+        Ok(parse_search(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    async fn is_playlist(&self, url: &str) -> Result<bool, String> {
+        let output = run_youtube_dl(
+            &["-J", "--flat-playlist", "--playlist-end=1", url],
+            config::timeouts::metadata(),
+        )
+        .await?;
+
+        let err_str = String::from_utf8_lossy(&output.stderr);
+        if !err_str.is_empty() {
+            error!("[playlist] {}", err_str);
+        }
+
+        parse_is_playlist(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    async fn get_playlist(
+        &self,
+        url: &str,
+        items: Option<&PlaylistItems>,
+    ) -> Result<LinkedList<MediaInfo>, String> {
+        let mut args = vec!["-j".to_string(), "--flat-playlist".to_string()];
+        if let Some(items) = items {
+            args.push(format!("--playlist-items={}", items.arg()));
+        }
+        args.push(url.to_string());
+
+        let flat_output = run_youtube_dl(
+            &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+            config::timeouts::playlist(),
+        )
+        .await?;
+
+        let err_str = String::from_utf8_lossy(&flat_output.stderr);
+        if !err_str.is_empty() {
+            error!("[playlist] {}", err_str);
+        }
+
+        let flat_output = String::from_utf8_lossy(&flat_output.stdout);
+
+        // the first song is looked up after the listing, which tells how many songs were picked
+        let first_items = match items {
+            Some(items) => items.first_arg(flat_output.lines().filter(|l| !l.is_empty()).count()),
+            None => "1".to_string(),
+        };
+
+        let first_output = run_youtube_dl(
+            &["-j", &format!("--playlist-items={}", first_items), url],
+            config::timeouts::metadata(),
+        )
+        .await?;
+
+        let err_str = String::from_utf8_lossy(&first_output.stderr);
+        if !err_str.is_empty() {
+            error!("[playlist] {}", err_str);
+        }
+
+        Ok(parse_playlist(
+            &String::from_utf8_lossy(&first_output.stdout),
+            &flat_output,
+        ))
+    }
+}
+
+/// Parses the output of `youtube-dl -j` for a single song
+pub fn parse_info(output_str: &str) -> Result<MediaInfo, String> {
+    let json_result: serde_json::Result<YoutubeDLJson> = serde_json::from_str(output_str);
+
+    match json_result {
+        Err(_) => {
+            error!("[metadata] [youtube-dl] [json parse error] {}", output_str);
+            Err("Unable to parse json".to_string())
+        }
+        Ok(json) => {
+            if json.url.is_none() && json.requested_formats.is_none() {
+                return Err("[metadata] [youtube-dl] Json returned no URL".to_string());
+            }
+
+            Ok(MediaInfo::from(json))
+        }
+    }
+}
+
+/// Parses the output of `youtube-dl -j --flat-playlist ytsearchN:<query>`
+pub fn parse_search(output: &str) -> Vec<MediaInfo> {
+    output
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(
+            |line| match serde_json::from_str::<YoutubeDLFlatJson>(line) {
+                Ok(json) => Some(MediaInfo::from(json)),
+                Err(err) => {
+                    error!("[search] {}", err);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Parses the output of `youtube-dl -j --playlist-items=1` for the first song, followed by the
+/// output of `youtube-dl -j --flat-playlist` for the rest
+pub fn parse_playlist(first_output: &str, flat_output: &str) -> LinkedList<MediaInfo> {
+    let mut sources: LinkedList<MediaInfo> = LinkedList::new();
+
+    if let Some(line) = first_output.lines().next() {
+        let json_result: serde_json::Result<YoutubeDLJson> = serde_json::from_str(line);
+
+        match json_result {
+            Ok(json) => sources.push_back(MediaInfo::from(json)),
+            Err(err) => error!("[playlist] {}", err),
+        }
+    } else {
+        error!("[playlist] First song is empty");
+    }
+
+    let mut lines = flat_output.lines();
+
+    // the first flat entry is the song looked up above, unless that lookup failed
+    if !sources.is_empty() {
+        lines.next();
+    }
+
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+
+        let json_result: serde_json::Result<YoutubeDLFlatJson> = serde_json::from_str(line);
+
+        match json_result {
+            Ok(json) => sources.push_back(MediaInfo::from(json)),
+            Err(err) => error!("[playlist] {}", err),
+        }
+    }
+
+    sources
+}
 
-            return Ok(sources);
+/// Parses the output of `youtube-dl -J --flat-playlist` to check if it's a playlist
+pub fn parse_is_playlist(output: &str) -> Result<bool, String> {
+    match serde_json::from_str::<YoutubeDLTypeJson>(output) {
+        Ok(json) => Ok(json.kind.as_deref() == Some("playlist")),
+        Err(_) => Err("Unable to parse json".to_string()),
+    }
+}
+
+/// Checks urls of known hosts without running youtube-dl.
+///
+/// Returns `None` if the host is unknown.
+pub fn match_playlist_url(url: &str) -> Option<bool> {
+    let url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+
+    let (host, path) = url.split_once('/').unwrap_or((url, ""));
+
+    match host {
+        "youtube.com" | "music.youtube.com" => {
+            Some(path.starts_with("playlist?") || path.contains("list="))
         }
+        "youtu.be" => Some(path.contains("list=")),
+        "soundcloud.com" => Some(path.contains("/sets/")),
+        host if host.ends_with(".bandcamp.com") => Some(path.starts_with("album/")),
+        _ => None,
     }
 }
 
-pub fn is_playlist(url: &String) -> bool {
-    return url.contains("youtube.com")
-        && (url.contains("/playlist?list=") || url.contains("&list="));
+/// Whether the query is a playlist url. Urls of unknown hosts are checked with youtube-dl.
+pub async fn is_playlist(provider: &dyn MetadataProvider, query: &str) -> bool {
+    if !strings::is_url(query) {
+        return false;
+    }
+
+    match match_playlist_url(query) {
+        Some(is_playlist) => is_playlist,
+        None => provider.is_playlist(query).await.unwrap_or_else(|err| {
+            warn!("[playlist] Unable to check {}: {}", query, err);
+            false
+        }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    mod search {
-        use super::super::get_search;
+    mod info {
+        use super::super::{parse_info, MetadataProvider};
+        use crate::media::fake_metadata::{FakeMetadataProvider, LIVE_URL, VIDEO_URL};
+
+        #[tokio::test]
+        async fn success() {
+            let video = FakeMetadataProvider::new()
+                .get_info(VIDEO_URL)
+                .await
+                .unwrap();
+
+            assert_eq!(video.url, VIDEO_URL);
+            assert_eq!(video.duration, 212);
+            assert!(video.playlist.is_none());
+            assert!(!video.live);
+        }
+
+        #[tokio::test]
+        async fn live() {
+            let video = FakeMetadataProvider::new()
+                .get_info(LIVE_URL)
+                .await
+                .unwrap();
+
+            assert_eq!(video.url, LIVE_URL);
+            assert_eq!(video.duration, 0);
+            assert!(video.live);
+        }
+
+        #[tokio::test]
+        async fn fail_not_url() {
+            let result = FakeMetadataProvider::new().get_info("amogus").await;
+
+            assert!(result.is_err());
+        }
 
         #[test]
-        fn success() {
-            let video = get_search(&"hello".to_string()).unwrap();
+        fn merged_formats() {
+            let output = r#"{"webpage_url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ", "requested_formats": [{"format_id": "248", "url": "https://rr4---sn-q4fl6nds.googlevideo.com/videoplayback?itag=248"}, {"format_id": "251", "url": "https://rr4---sn-q4fl6nds.googlevideo.com/videoplayback?itag=251"}]}"#;
 
-            assert!(!video.url.is_empty());
+            assert!(parse_info(output).is_ok());
+        }
+
+        #[test]
+        fn fail_no_stream() {
+            let output = r#"{"webpage_url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ", "title": "No formats"}"#;
+
+            assert!(parse_info(output).is_err());
         }
     }
 
-    mod playlist {
-        use super::super::{get_playlist, is_playlist};
+    mod search {
+        use super::super::{parse_search_backends, parse_search_prefix, search, SearchBackend};
+        use crate::media::fake_metadata::{FakeMetadataProvider, SEARCH_QUERY, SOUNDCLOUD_QUERY};
+
+        #[tokio::test]
+        async fn success() {
+            let video = search(
+                &FakeMetadataProvider::new(),
+                SEARCH_QUERY,
+                &[SearchBackend::YouTube],
+            )
+            .await
+            .unwrap();
+
+            assert!(!video.url.is_empty());
+        }
+
+        #[tokio::test]
+        async fn fallback() {
+            let video = search(
+                &FakeMetadataProvider::new(),
+                SEARCH_QUERY,
+                &[SearchBackend::SoundCloud, SearchBackend::YouTube],
+            )
+            .await
+            .unwrap();
+
+            assert!(video.url.contains("youtube.com"));
+        }
+
+        #[tokio::test]
+        async fn fallback_to_soundcloud() {
+            let video = search(
+                &FakeMetadataProvider::new(),
+                SOUNDCLOUD_QUERY,
+                &[SearchBackend::YouTube, SearchBackend::SoundCloud],
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                video.url,
+                "https://soundcloud.com/hiroshi-yoshimura-official/blink"
+            );
+        }
+
+        #[tokio::test]
+        async fn fallback_exhausted() {
+            let result = search(
+                &FakeMetadataProvider::new(),
+                SEARCH_QUERY,
+                &[SearchBackend::SoundCloud, SearchBackend::Bilibili],
+            )
+            .await;
+
+            assert!(result.is_err());
+        }
+
+        #[tokio::test]
+        async fn prefix_skips_fallback() {
+            let result = search(
+                &FakeMetadataProvider::new(),
+                &format!("sc:{}", SEARCH_QUERY),
+                &[SearchBackend::YouTube],
+            )
+            .await;
+
+            assert!(result.is_err());
+        }
+
+        #[tokio::test]
+        async fn prefix_overrides_backends() {
+            let video = search(
+                &FakeMetadataProvider::new(),
+                &format!("yt: {}", SEARCH_QUERY),
+                &[SearchBackend::SoundCloud],
+            )
+            .await
+            .unwrap();
+
+            assert!(!video.url.is_empty());
+        }
 
         #[test]
-        fn success_page() {
-            let sources = get_playlist(
-                &"https://www.youtube.com/playlist?list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS"
-                    .to_string(),
+        fn parse_prefix() {
+            assert_eq!(
+                parse_search_prefix("sc:hello world"),
+                (Some(SearchBackend::SoundCloud), "hello world")
+            );
+            assert_eq!(
+                parse_search_prefix("youtube-music: hello"),
+                (Some(SearchBackend::YouTubeMusic), "hello")
+            );
+        }
+
+        #[test]
+        fn parse_no_prefix() {
+            assert_eq!(parse_search_prefix("hello"), (None, "hello"));
+            assert_eq!(parse_search_prefix("re: hello"), (None, "re: hello"));
+            assert_eq!(parse_search_prefix("sc:"), (None, "sc:"));
+        }
+
+        #[test]
+        fn parse_backends() {
+            assert_eq!(
+                parse_search_backends("sc, youtube sc"),
+                Ok(vec![SearchBackend::SoundCloud, SearchBackend::YouTube])
+            );
+            assert_eq!(parse_search_backends(""), Ok(vec![]));
+            assert!(parse_search_backends("youtube,spotify").is_err());
+        }
+    }
+
+    mod search_results {
+        use super::super::{search_results, SearchBackend};
+        use crate::media::fake_metadata::{FakeMetadataProvider, SEARCH_QUERY};
+
+        #[tokio::test]
+        async fn success() {
+            let results = search_results(
+                &FakeMetadataProvider::new(),
+                SEARCH_QUERY,
+                5,
+                &[SearchBackend::SoundCloud, SearchBackend::YouTube],
             )
+            .await
             .unwrap();
 
-            assert!(sources.len() > 0);
-            for source in sources {
-                assert!(!source.url.is_empty())
+            assert_eq!(results.len(), 5);
+            for result in results {
+                assert!(!result.url.is_empty());
+                assert!(!result.title.is_empty());
+                assert!(!result.uploader.is_empty());
             }
         }
 
-        #[test]
-        fn success_video() {
-            let sources = get_playlist(
-                &"https://www.youtube.com/watch?v=nBpgoga0FZ4&list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS".to_string()
+        #[tokio::test]
+        async fn no_results() {
+            let results = search_results(
+                &FakeMetadataProvider::new(),
+                "amogus",
+                5,
+                &[SearchBackend::YouTube],
             )
+            .await
             .unwrap();
 
+            assert!(results.is_empty());
+        }
+    }
+
+    mod playlist {
+        use super::super::{is_playlist, match_playlist_url, MetadataProvider};
+        use crate::media::fake_metadata::{
+            FakeMetadataProvider, MIXCLOUD_PLAYLIST_URL, PLAYLIST_URL, PLAYLIST_VIDEO_URL,
+            VIDEO_URL,
+        };
+
+        #[tokio::test]
+        async fn success_page() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(PLAYLIST_URL, None)
+                .await
+                .unwrap();
+
+            assert_eq!(sources.len(), 3);
+            assert!(sources.front().unwrap().playlist.is_some());
+            assert!(!sources.front().unwrap().partial);
+            for source in sources.iter().skip(1) {
+                assert!(source.partial);
+                assert!(source.thumbnail.contains("/hqdefault.jpg"));
+            }
+            for source in sources {
+                assert!(!source.url.is_empty())
+            }
+        }
+
+        #[tokio::test]
+        async fn success_video() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(PLAYLIST_VIDEO_URL, None)
+                .await
+                .unwrap();
+
             assert!(sources.len() > 0);
             for source in sources {
                 assert!(!source.url.is_empty())
             }
         }
 
-        #[test]
-        fn fail_video_url() {
-            let sources =
-                get_playlist(&"https://www.youtube.com/watch?v=6YBDo5S8soo".to_string()).unwrap();
+        #[tokio::test]
+        async fn fail_video_url() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(VIDEO_URL, None)
+                .await
+                .unwrap();
 
             assert_eq!(sources.len(), 1);
         }
 
-        #[test]
-        fn fail_not_url() {
-            let sources = get_playlist(&"amogus".to_string()).unwrap();
+        #[tokio::test]
+        async fn fail_not_url() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist("amogus", None)
+                .await
+                .unwrap();
 
             assert!(sources.is_empty());
         }
+        #[tokio::test]
+        async fn success_other_host() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(MIXCLOUD_PLAYLIST_URL, None)
+                .await
+                .unwrap();
+
+            assert_eq!(sources.len(), 3);
+            assert!(sources.front().unwrap().playlist.is_some());
+            for source in sources {
+                assert!(source.url.starts_with("https://www.mixcloud.com/"))
+            }
+        }
 
         #[test]
-        fn is_playlist_playlist_page() {
-            assert!(is_playlist(
-                &"https://www.youtube.com/playlist?list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS"
-                    .to_string()
-            ))
+        fn match_youtube() {
+            assert_eq!(match_playlist_url(PLAYLIST_URL), Some(true));
+            assert_eq!(match_playlist_url(PLAYLIST_VIDEO_URL), Some(true));
+            assert_eq!(match_playlist_url(VIDEO_URL), Some(false));
         }
 
         #[test]
-        fn is_playlist_video_page() {
-            assert!(is_playlist(&"https://www.youtube.com/watch?v=nBpgoga0FZ4&list=PLdY_Mca8fL_BbtQrKu9lm-LcCcY-t2mVS".to_string()))
+        fn match_youtube_music() {
+            assert_eq!(
+                match_playlist_url("https://music.youtube.com/playlist?list=OLAK5uy_abc"),
+                Some(true)
+            );
+            assert_eq!(
+                match_playlist_url("https://music.youtube.com/watch?v=6YBDo5S8soo"),
+                Some(false)
+            );
         }
 
         #[test]
-        fn is_playlist_not_video() {
-            assert!(!is_playlist(
-                &"https://www.youtube.com/watch?v=6YBDo5S8soo".to_string()
-            ))
+        fn match_short_url() {
+            assert_eq!(
+                match_playlist_url("https://youtu.be/nBpgoga0FZ4?list=PLdY_Mca8fL_BbtQrKu9lm"),
+                Some(true)
+            );
+            assert_eq!(
+                match_playlist_url("https://youtu.be/6YBDo5S8soo"),
+                Some(false)
+            );
+        }
+
+        #[test]
+        fn match_soundcloud() {
+            assert_eq!(
+                match_playlist_url("https://soundcloud.com/artist/sets/album"),
+                Some(true)
+            );
+            assert_eq!(
+                match_playlist_url("https://soundcloud.com/artist/track"),
+                Some(false)
+            );
+        }
+
+        #[test]
+        fn match_bandcamp() {
+            assert_eq!(
+                match_playlist_url("https://artist.bandcamp.com/album/album"),
+                Some(true)
+            );
+            assert_eq!(
+                match_playlist_url("https://artist.bandcamp.com/track/track"),
+                Some(false)
+            );
+        }
+
+        #[test]
+        fn match_unknown_host() {
+            assert_eq!(match_playlist_url(MIXCLOUD_PLAYLIST_URL), None);
+        }
+
+        #[tokio::test]
+        async fn is_playlist_unknown_host() {
+            let provider = FakeMetadataProvider::new();
+
+            assert!(is_playlist(&provider, MIXCLOUD_PLAYLIST_URL).await);
+            assert!(!is_playlist(&provider, "https://example.com/song").await);
+        }
+
+        #[tokio::test]
+        async fn is_playlist_not_url() {
+            assert!(!is_playlist(&FakeMetadataProvider::new(), "hello").await);
+        }
+    }
+
+    mod playlist_items {
+        use super::super::{parse_playlist, parse_playlist_items, MetadataProvider, PlaylistItems};
+        use crate::media::fake_metadata::{FakeMetadataProvider, PLAYLIST_URL};
+
+        #[test]
+        fn parse_range() {
+            let items = PlaylistItems::parse("1-20").unwrap();
+
+            assert_eq!(items, PlaylistItems::Ranges(vec![(1, 20)]));
+            assert_eq!(items.arg(), "1-20");
+            assert_eq!(items.first_arg(20), "1");
+        }
+
+        #[test]
+        fn parse_selection() {
+            let items = PlaylistItems::parse("5, 7,9-10").unwrap();
+
+            assert_eq!(items, PlaylistItems::Ranges(vec![(5, 5), (7, 7), (9, 10)]));
+            assert_eq!(items.arg(), "5,7,9-10");
+            assert_eq!(items.first_arg(3), "5");
+            assert_eq!(items.to_string(), "5, 7, 9-10");
+        }
+
+        #[test]
+        fn parse_last() {
+            let items = PlaylistItems::parse("last 10").unwrap();
+
+            assert_eq!(items, PlaylistItems::Last(10));
+            assert_eq!(items.arg(), "-10:");
+            assert_eq!(items.first_arg(10), "-10");
+        }
+
+        #[test]
+        fn last_of_short_playlist() {
+            let items = PlaylistItems::Last(10);
+
+            // a playlist of 3 songs only lists 3 for `-10:`, and has no song `-10`
+            assert_eq!(items.first_arg(3), "-3");
+        }
+
+        #[test]
+        fn parse_without_first_song() {
+            let sources = parse_playlist("", include_str!("fixtures/playlist_flat.json"));
+
+            assert_eq!(sources.len(), 3);
+            assert!(sources.front().unwrap().url.ends_with("djV11Xbc914"));
+        }
+
+        #[test]
+        fn parse_invalid() {
+            assert!(PlaylistItems::parse("0-5").is_err());
+            assert!(PlaylistItems::parse("5-1").is_err());
+            assert!(PlaylistItems::parse("last").is_err());
+            assert!(PlaylistItems::parse("some").is_err());
+        }
+
+        #[test]
+        fn split_from_query() {
+            assert_eq!(
+                parse_playlist_items(&format!("{} last 2", PLAYLIST_URL)),
+                Ok((PLAYLIST_URL, Some(PlaylistItems::Last(2))))
+            );
+            assert_eq!(parse_playlist_items(PLAYLIST_URL), Ok((PLAYLIST_URL, None)));
+            assert_eq!(
+                parse_playlist_items("never gonna 1-2"),
+                Ok(("never gonna 1-2", None))
+            );
+        }
+
+        #[tokio::test]
+        async fn get_selection() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(PLAYLIST_URL, Some(&PlaylistItems::parse("2-3").unwrap()))
+                .await
+                .unwrap();
+
+            assert_eq!(sources.len(), 2);
+            assert!(sources.front().unwrap().url.ends_with("FTQbiNvZqaY"));
+            assert!(sources.front().unwrap().playlist.is_some());
+        }
+
+        #[tokio::test]
+        async fn get_last() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(PLAYLIST_URL, Some(&PlaylistItems::Last(1)))
+                .await
+                .unwrap();
+
+            assert_eq!(sources.len(), 1);
+            assert!(sources.front().unwrap().url.ends_with("1k8craCGpgs"));
+        }
+
+        #[tokio::test]
+        async fn get_last_of_short_playlist() {
+            let sources = FakeMetadataProvider::new()
+                .get_playlist(PLAYLIST_URL, Some(&PlaylistItems::Last(10)))
+                .await
+                .unwrap();
+
+            assert_eq!(sources.len(), 3);
+            assert!(sources.front().unwrap().playlist.is_some());
         }
     }
 }
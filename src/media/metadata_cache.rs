@@ -0,0 +1,327 @@
+use poise::async_trait;
+use std::{
+    collections::{HashMap, LinkedList},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+use crate::{config, database::plugin::DatabasePlugin};
+
+use super::{
+    media_info::MediaInfo,
+    metadata::{MetadataProvider, PlaylistItems, SearchBackend},
+};
+
+/// Where cached values are kept
+enum CacheStore {
+    Database(Arc<dyn DatabasePlugin>),
+    /// Used when the database is disabled
+    Memory(Mutex<HashMap<String, (String, Instant)>>),
+}
+
+impl CacheStore {
+    fn get(&self, key: &str, max_age: Duration) -> Option<String> {
+        match self {
+            CacheStore::Database(db) => db.get_cached(key, max_age).unwrap_or_else(|err| {
+                error!("[cache] {}", err);
+                None
+            }),
+            CacheStore::Memory(map) => {
+                let mut map = map.lock().unwrap();
+
+                match map.get(key) {
+                    Some((value, cached_at)) if cached_at.elapsed() < max_age => {
+                        Some(value.clone())
+                    }
+                    Some(_) => {
+                        map.remove(key);
+                        None
+                    }
+                    None => None,
+                }
+            }
+        }
+    }
+
+    /// Stores a value and drops the ones older than `max_age`
+    fn set(&self, key: String, value: String, max_age: Duration) {
+        match self {
+            CacheStore::Database(db) => {
+                if let Err(err) = db.set_cached(&key, &value, max_age) {
+                    error!("[cache] {}", err);
+                }
+            }
+            CacheStore::Memory(map) => {
+                let mut map = map.lock().unwrap();
+
+                map.retain(|_, (_, cached_at)| cached_at.elapsed() < max_age);
+                map.insert(key, (value, Instant::now()));
+            }
+        }
+    }
+
+    fn purge(&self) -> Result<usize, String> {
+        match self {
+            CacheStore::Database(db) => db.purge_cache().map_err(|err| err.to_string()),
+            CacheStore::Memory(map) => {
+                let mut map = map.lock().unwrap();
+                let count = map.len();
+                map.clear();
+                Ok(count)
+            }
+        }
+    }
+}
+
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub in_memory: bool,
+}
+
+impl CacheStats {
+    /// Share of lookups served from the cache, between 0 and 1
+    pub fn hit_rate(&self) -> f32 {
+        if self.hits + self.misses == 0 {
+            0.0
+        } else {
+            self.hits as f32 / (self.hits + self.misses) as f32
+        }
+    }
+}
+
+/// Serves song and search lookups from a cache before asking another provider.
+///
+/// Searches are cached as the url of the result, so they share the song's cached metadata.
+pub struct CachedMetadataProvider {
+    provider: Arc<dyn MetadataProvider>,
+    store: CacheStore,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CachedMetadataProvider {
+    /// Caches in the database, or in memory if the database is disabled
+    pub fn new(provider: Arc<dyn MetadataProvider>, db: Arc<dyn DatabasePlugin>) -> Self {
+        let store = if db.disabled() {
+            CacheStore::Memory(Mutex::new(HashMap::new()))
+        } else {
+            CacheStore::Database(db)
+        };
+
+        CachedMetadataProvider {
+            provider,
+            store,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            in_memory: matches!(self.store, CacheStore::Memory(_)),
+        }
+    }
+
+    /// Removes every cached lookup and resets the stats. Returns how many were removed.
+    pub fn purge(&self) -> Result<usize, String> {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+
+        self.store.purge()
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        let ttl = config::cache::metadata_ttl()?;
+
+        let value = self.store.get(key, ttl);
+
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        value
+    }
+
+    fn set(&self, key: String, value: String) {
+        if let Some(ttl) = config::cache::metadata_ttl() {
+            self.store.set(key, value, ttl);
+        }
+    }
+
+    /// Caches the song under the url it was looked up with, and its own url if different
+    fn set_info(&self, url: &str, info: &MediaInfo) {
+        let json = match serde_json::to_string(info) {
+            Ok(json) => json,
+            Err(err) => {
+                error!("[cache] Unable to serialize {}: {}", info.url, err);
+                return;
+            }
+        };
+
+        if info.url != url {
+            self.set(info_key(&info.url), json.clone());
+        }
+        self.set(info_key(url), json);
+    }
+}
+
+fn info_key(url: &str) -> String {
+    format!("url:{}", url)
+}
+
+fn search_key(query: &str, backend: SearchBackend) -> String {
+    format!("search:{}:{}", backend.name(), query.trim().to_lowercase())
+}
+
+#[async_trait]
+impl MetadataProvider for CachedMetadataProvider {
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String> {
+        if let Some(json) = self.get(&info_key(url)) {
+            if let Ok(info) = serde_json::from_str::<MediaInfo>(&json) {
+                return Ok(info);
+            }
+        }
+
+        let info = self.provider.get_info(url).await?;
+
+        self.set_info(url, &info);
+
+        Ok(info)
+    }
+
+    async fn get_search(&self, query: &str, backend: SearchBackend) -> Result<MediaInfo, String> {
+        let key = search_key(query, backend);
+
+        if let Some(url) = self.get(&key) {
+            return self.get_info(&url).await;
+        }
+
+        let info = self.provider.get_search(query, backend).await?;
+
+        self.set(key, info.url.clone());
+        self.set_info(&info.url, &info);
+
+        Ok(info)
+    }
+
+    async fn get_search_results(
+        &self,
+        query: &str,
+        backend: SearchBackend,
+        count: usize,
+    ) -> Result<Vec<MediaInfo>, String> {
+        self.provider
+            .get_search_results(query, backend, count)
+            .await
+    }
+
+    async fn is_playlist(&self, url: &str) -> Result<bool, String> {
+        self.provider.is_playlist(url).await
+    }
+
+    async fn get_playlist(
+        &self,
+        url: &str,
+        items: Option<&PlaylistItems>,
+    ) -> Result<LinkedList<MediaInfo>, String> {
+        self.provider.get_playlist(url, items).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::sqlite_plugin::SQLitePlugin,
+        media::fake_metadata::{FakeMetadataProvider, SEARCH_QUERY, VIDEO_URL},
+    };
+
+    fn mock_cache() -> CachedMetadataProvider {
+        CachedMetadataProvider::new(
+            Arc::new(FakeMetadataProvider::new()),
+            Arc::new(SQLitePlugin {
+                path: "".to_string(),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn info_is_cached() {
+        let cache = mock_cache();
+
+        let first = cache.get_info(VIDEO_URL).await.unwrap();
+        let second = cache.get_info(VIDEO_URL).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+        assert!(cache.stats().in_memory);
+    }
+
+    #[tokio::test]
+    async fn search_is_cached() {
+        let cache = mock_cache();
+
+        let first = cache
+            .get_search(SEARCH_QUERY, SearchBackend::YouTube)
+            .await
+            .unwrap();
+        let second = cache
+            .get_search(&SEARCH_QUERY.to_uppercase(), SearchBackend::YouTube)
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        // Search and song lookups both hit
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_cached() {
+        let cache = mock_cache();
+
+        assert!(cache.get_info("amogus").await.is_err());
+        assert!(cache.get_info("amogus").await.is_err());
+
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[tokio::test]
+    async fn purge() {
+        let cache = mock_cache();
+
+        cache.get_info(VIDEO_URL).await.unwrap();
+
+        assert_eq!(cache.purge(), Ok(1));
+        assert_eq!(cache.stats().hits + cache.stats().misses, 0);
+
+        cache.get_info(VIDEO_URL).await.unwrap();
+
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn memory_drops_expired() {
+        let store = CacheStore::Memory(Mutex::new(HashMap::new()));
+        let max_age = Duration::from_millis(20);
+
+        store.set("old".to_string(), "value".to_string(), max_age);
+        std::thread::sleep(max_age);
+        store.set("new".to_string(), "value".to_string(), max_age);
+
+        match &store {
+            CacheStore::Memory(map) => assert_eq!(map.lock().unwrap().len(), 1),
+            CacheStore::Database(_) => unreachable!(),
+        }
+        assert_eq!(store.get("new", max_age), Some("value".to_string()));
+    }
+}
@@ -1,5 +1,13 @@
+pub mod audio_cache;
+pub mod audio_source;
 pub mod commands;
+#[cfg(test)]
+pub mod fake_metadata;
 pub mod global_media_player;
+pub mod library;
 pub mod media_info;
 pub mod metadata;
+pub mod metadata_cache;
 pub mod plugin;
+pub mod probe;
+pub mod snapshots;
@@ -2,7 +2,10 @@ use std::sync::Arc;
 
 use poise::serenity_prelude as serenity;
 
-use super::global_media_player::GlobalMediaPlayer;
+use super::{
+    global_media_player::GlobalMediaPlayer, metadata::MetadataProvider,
+    metadata_cache::CachedMetadataProvider,
+};
 
 pub struct GlobalMediaPlayerKey;
 
@@ -32,3 +35,49 @@ pub async fn get_media_player(ctx: &serenity::Context) -> Option<Arc<GlobalMedia
 
     data.get::<GlobalMediaPlayerKey>().cloned()
 }
+
+pub struct MetadataProviderKey;
+
+impl serenity::TypeMapKey for MetadataProviderKey {
+    type Value = Arc<dyn MetadataProvider>;
+}
+
+pub trait MetadataProviderInit {
+    fn register_metadata_provider(self, provider: Arc<dyn MetadataProvider>) -> Self;
+}
+
+impl MetadataProviderInit for serenity::ClientBuilder {
+    fn register_metadata_provider(self, provider: Arc<dyn MetadataProvider>) -> Self {
+        self.type_map_insert::<MetadataProviderKey>(provider)
+    }
+}
+
+pub async fn get_metadata_provider(ctx: &serenity::Context) -> Option<Arc<dyn MetadataProvider>> {
+    let data = ctx.data.read().await;
+
+    data.get::<MetadataProviderKey>().cloned()
+}
+
+pub struct MetadataCacheKey;
+
+impl serenity::TypeMapKey for MetadataCacheKey {
+    type Value = Arc<CachedMetadataProvider>;
+}
+
+pub trait MetadataCacheInit {
+    /// Registers the cache as the metadata provider, and for the cache debug command
+    fn register_metadata_cache(self, cache: Arc<CachedMetadataProvider>) -> Self;
+}
+
+impl MetadataCacheInit for serenity::ClientBuilder {
+    fn register_metadata_cache(self, cache: Arc<CachedMetadataProvider>) -> Self {
+        self.register_metadata_provider(cache.clone())
+            .type_map_insert::<MetadataCacheKey>(cache)
+    }
+}
+
+pub async fn get_metadata_cache(ctx: &serenity::Context) -> Option<Arc<CachedMetadataProvider>> {
+    let data = ctx.data.read().await;
+
+    data.get::<MetadataCacheKey>().cloned()
+}
@@ -0,0 +1,523 @@
+use poise::async_trait;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, LOCATION},
+    redirect, Client, Response,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    process::{Output, Stdio},
+};
+use tokio::{io::AsyncWriteExt, net, process};
+use tracing::error;
+use url::{Host, Url};
+
+use crate::{config, utils::strings};
+
+use super::media_info::{MediaInfo, MediaSource};
+
+/// Extensions of files that are played without youtube-dl
+pub const AUDIO_EXTENSIONS: [&str; 10] = [
+    "mp3", "flac", "ogg", "oga", "opus", "wav", "m4a", "aac", "mka", "webm",
+];
+
+/// Protocols ffmpeg and ffprobe may use for links, which are piped to them. Playlists in the
+/// response can't make them open other urls or local files.
+pub const PIPE_PROTOCOLS: &str = "pipe";
+
+#[derive(Deserialize)]
+struct FFProbeJson {
+    format: FFProbeFormat,
+}
+
+#[derive(Deserialize)]
+struct FFProbeFormat {
+    duration: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Whether the path, or the path of the url, ends in an audio file extension
+pub fn has_audio_extension(path: &str) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    match path.rsplit_once('.') {
+        Some((_, extension)) => AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Whether the url links to an audio file, which ffmpeg can play without youtube-dl
+pub fn is_direct_url(url: &str) -> bool {
+    strings::is_url(url) && has_audio_extension(url)
+}
+
+/// Whether the address is on the bot's machine or network
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                // this network 0.0.0.0/8
+                || first == 0
+                // shared address space 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64)
+                // benchmarking 198.18.0.0/15
+                || (first == 198 && second & 0xfe == 18)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let embedded = |high: u16, low: u16| {
+                is_internal(IpAddr::V4(Ipv4Addr::from((high as u32) << 16 | low as u32)))
+            };
+
+            match ip.to_ipv4_mapped() {
+                Some(ip) => is_internal(IpAddr::V4(ip)),
+                // NAT64 64:ff9b::/96 reaches the address in its last 32 bits
+                None if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] => {
+                    embedded(segments[6], segments[7])
+                }
+                // 6to4 2002::/16 reaches the address in the 32 bits after the prefix
+                None if segments[0] == 0x2002 => embedded(segments[1], segments[2]),
+                None => {
+                    ip.is_loopback()
+                        || ip.is_unspecified()
+                        // unique local fc00::/7
+                        || segments[0] & 0xfe00 == 0xfc00
+                        // link local fe80::/10
+                        || segments[0] & 0xffc0 == 0xfe80
+                }
+            }
+        }
+    }
+}
+
+/// Checks that every address the url's host resolves to is public, so links can't reach the
+/// bot's machine or network. Returns the domain with its addresses, which requests must connect
+/// to instead of resolving the domain again.
+pub async fn check_host(url: &str) -> Result<Option<(String, Vec<SocketAddr>)>, String> {
+    let invalid = || "Invalid link".to_string();
+
+    let url = Url::parse(url).map_err(|_| invalid())?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(invalid());
+    }
+    let port = url.port_or_known_default().ok_or_else(invalid)?;
+
+    let (domain, addresses): (Option<&str>, Vec<SocketAddr>) = match url.host() {
+        Some(Host::Domain(domain)) => (
+            Some(domain),
+            net::lookup_host((domain, port))
+                .await
+                .map_err(|_| format!("Unable to find {}", domain))?
+                .collect(),
+        ),
+        Some(Host::Ipv4(ip)) => (None, vec![SocketAddr::new(IpAddr::V4(ip), port)]),
+        Some(Host::Ipv6(ip)) => (None, vec![SocketAddr::new(IpAddr::V6(ip), port)]),
+        None => return Err(invalid()),
+    };
+
+    if addresses.is_empty() || addresses.iter().any(|address| is_internal(address.ip())) {
+        return Err("Links to private networks can't be played".to_string());
+    }
+
+    Ok(domain.map(|domain| (domain.to_string(), addresses)))
+}
+
+/// Whether the response headers are of audio, or of an Icecast or Shoutcast radio stream
+fn is_audio_response(headers: &HeaderMap) -> bool {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    content_type.starts_with("audio/")
+        || content_type.starts_with("application/ogg")
+        || headers.contains_key("icy-name")
+        || headers.contains_key("icy-br")
+}
+
+/// Most redirects followed to find where a url's audio is
+const MAX_REDIRECTS: usize = 5;
+
+/// Opens the url, following its redirects and checking every hop with `check_host`. Each hop
+/// connects to the addresses that were checked, so the host can't resolve to another address
+/// after the check. Only the headers of the response are read.
+pub async fn open(url: &str) -> Result<Response, String> {
+    let unreachable = || "Unable to open the link".to_string();
+    let timeout = config::timeouts::metadata();
+
+    let mut url = url.to_string();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let mut client = Client::builder()
+            .redirect(redirect::Policy::none())
+            .connect_timeout(timeout);
+        if let Some((domain, addresses)) = check_host(&url).await? {
+            client = client.resolve_to_addrs(&domain, &addresses);
+        }
+        let client = client.build().map_err(|_| unreachable())?;
+
+        // the body isn't read yet, which streams may take forever to finish
+        let response = match tokio::time::timeout(timeout, client.get(&url).send()).await {
+            Ok(response) => response.map_err(|_| unreachable())?,
+            Err(_) => return Err(unreachable()),
+        };
+
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+
+        url = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| Url::parse(&url).ok()?.join(location).ok())
+            .ok_or_else(unreachable)?
+            .to_string();
+    }
+
+    Err("The link redirects too many times".to_string())
+}
+
+/// Writes the body of an opened link to ffmpeg or ffprobe in a task of its own, until the body
+/// ends, stops sending for longer than `config::timeouts::read`, or the process stops reading it.
+/// They don't get the url, so they can't resolve the host themselves.
+pub fn pipe_to(mut response: Response, mut stdin: process::ChildStdin) {
+    let timeout = config::timeouts::read();
+
+    tokio::spawn(async move {
+        while let Ok(Ok(Some(chunk))) = tokio::time::timeout(timeout, response.chunk()).await {
+            if stdin.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Whether a public url serves audio, after following its redirects
+pub async fn is_audio_stream(url: &str) -> bool {
+    match open(url).await {
+        Ok(response) => is_audio_response(response.headers()),
+        Err(_) => false,
+    }
+}
+
+/// Reads songs from links that youtube-dl can't play, which are played with ffmpeg directly
+#[async_trait]
+pub trait UrlProbe: Send + Sync {
+    /// Refuses links to the bot's machine or network, before youtube-dl or ffmpeg open them
+    async fn check_host(&self, url: &str) -> Result<(), String>;
+    /// Whether the url serves audio, so it's worth probing when youtube-dl can't play it
+    async fn is_audio_stream(&self, url: &str) -> bool;
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String>;
+}
+
+/// Probes links with ffprobe
+pub struct FFProbe;
+
+#[async_trait]
+impl UrlProbe for FFProbe {
+    async fn check_host(&self, url: &str) -> Result<(), String> {
+        check_host(url).await.map(|_| ())
+    }
+
+    async fn is_audio_stream(&self, url: &str) -> bool {
+        is_audio_stream(url).await
+    }
+
+    async fn get_info(&self, url: &str) -> Result<MediaInfo, String> {
+        get_info(url, url, MediaSource::Direct).await
+    }
+}
+
+/// Name of the file at the end of a path or url, without its extension
+fn file_stem(path: &str) -> &str {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let name = path
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(path);
+
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+/// Parses `ffprobe -show_format` output. Songs without title tags are named after the file,
+/// and radio streams after their station.
+pub fn parse_probe(output: &str, url: &str, source: MediaSource) -> Result<MediaInfo, String> {
+    let json = match serde_json::from_str::<FFProbeJson>(output) {
+        Ok(json) => json,
+        Err(_) => return Err("Unable to play the file".to_string()),
+    };
+
+    // tag names are upper case in some containers
+    let tags: HashMap<String, String> = json
+        .format
+        .tags
+        .into_iter()
+        .map(|(name, value)| (name.to_lowercase(), value))
+        .filter(|(_, value)| !value.trim().is_empty())
+        .collect();
+    let tag = |names: &[&str]| names.iter().find_map(|name| tags.get(*name)).cloned();
+
+    let duration = json
+        .format
+        .duration
+        .and_then(|duration| duration.parse::<f64>().ok());
+
+    Ok(MediaInfo {
+        url: url.to_string(),
+        title: tag(&["title", "icy-name"]).unwrap_or_else(|| file_stem(url).to_string()),
+        duration: duration.unwrap_or_default() as i64,
+        description: tag(&["comment", "icy-description"]).unwrap_or_default(),
+        uploader: tag(&["artist", "album_artist", "icy-genre"]).unwrap_or_default(),
+        source,
+        // radio streams don't end
+        live: duration.is_none(),
+        ..MediaInfo::empty()
+    })
+}
+
+/// Reads the metadata of a file or url with ffprobe. `url` is the url the song is queued with.
+///
+/// Direct urls must be public, and are opened with `open` and piped to ffprobe.
+pub async fn get_info(
+    input: impl AsRef<OsStr>,
+    url: &str,
+    source: MediaSource,
+) -> Result<MediaInfo, String> {
+    let mut content_length = None;
+
+    let output = match source {
+        MediaSource::Direct => {
+            let response = open(url).await?;
+            content_length = response.content_length();
+            run_ffprobe(OsStr::new("pipe:0"), Some(response)).await?
+        }
+        _ => run_ffprobe(input.as_ref(), None).await?,
+    };
+
+    if !output.status.success() {
+        error!(
+            "[metadata] [ffprobe] {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        return Err("Unable to play the file".to_string());
+    }
+
+    let mut info = parse_probe(&String::from_utf8_lossy(&output.stdout), url, source)?;
+
+    // ffprobe can't find the duration of some formats when they are piped, but radio streams
+    // don't have a length
+    if content_length.is_some() {
+        info.live = false;
+    }
+
+    Ok(info)
+}
+
+/// Runs ffprobe on the input, or on the body of `response` piped to it if there is one
+async fn run_ffprobe(input: &OsStr, response: Option<Response>) -> Result<Output, String> {
+    let timeout = config::timeouts::metadata();
+
+    let mut ffprobe = process::Command::new("ffprobe");
+    ffprobe.args(["-v", "error", "-print_format", "json", "-show_format"]);
+
+    if response.is_some() {
+        ffprobe
+            .args(["-protocol_whitelist", PIPE_PROTOCOLS])
+            .stdin(Stdio::piped());
+    } else {
+        ffprobe.stdin(Stdio::null());
+    }
+
+    let mut child = match ffprobe
+        .arg(input)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(_) => return Err("Failed to run ffprobe".to_string()),
+    };
+
+    // ffprobe stops reading once it found the format, which ends the copy
+    if let (Some(response), Some(stdin)) = (response, child.stdin.take()) {
+        pipe_to(response, stdin);
+    }
+
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(_)) => Err("Failed to run ffprobe".to_string()),
+        Err(_) => Err(format!(
+            "Looking up the song took longer than {} seconds",
+            timeout.as_secs()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_JSON: &str = include_str!("fixtures/ffprobe_file.json");
+    const RADIO_JSON: &str = include_str!("fixtures/ffprobe_radio.json");
+    const UNTAGGED_JSON: &str = include_str!("fixtures/ffprobe_untagged.json");
+
+    #[test]
+    fn audio_extension() {
+        assert!(has_audio_extension("music/song.mp3"));
+        assert!(has_audio_extension("music/SONG.FLAC"));
+        assert!(has_audio_extension("https://example.com/song.ogg?token=1"));
+        assert!(!has_audio_extension("music/cover.jpg"));
+        assert!(!has_audio_extension("https://example.com/stream"));
+    }
+
+    #[test]
+    fn direct_url() {
+        assert!(is_direct_url("https://example.com/song.mp3"));
+        assert!(is_direct_url("http://example.com/song.mp3"));
+        assert!(!is_direct_url("song.mp3"));
+        assert!(!is_direct_url(
+            "https://www.youtube.com/watch?v=6YBDo5S8soo"
+        ));
+    }
+
+    #[test]
+    fn tagged_file() {
+        let url = "local:Daft Punk/Discovery/01 One More Time.flac";
+        let info = parse_probe(FILE_JSON, url, MediaSource::Library).unwrap();
+
+        assert_eq!(info.url, url);
+        assert_eq!(info.title, "One More Time");
+        assert_eq!(info.uploader, "Daft Punk");
+        assert_eq!(info.duration, 320);
+        assert_eq!(info.source, MediaSource::Library);
+    }
+
+    #[test]
+    fn untagged_file() {
+        let url = "https://cdn.discordapp.com/attachments/1083417466433523822/1271891040436404296/\
+                   voice-message.ogg?ex=66b8f6e4";
+        let info = parse_probe(UNTAGGED_JSON, url, MediaSource::Direct).unwrap();
+
+        assert_eq!(info.url, url);
+        assert_eq!(info.title, "voice-message");
+        assert_eq!(info.uploader, "");
+        assert_eq!(info.duration, 42);
+        assert!(!info.live);
+    }
+
+    #[test]
+    fn radio_stream() {
+        let info = parse_probe(
+            RADIO_JSON,
+            "https://ice1.somafm.com/groovesalad-128-mp3",
+            MediaSource::Direct,
+        )
+        .unwrap();
+
+        assert_eq!(
+            info.title,
+            "Groove Salad: a nicely chilled plate of ambient beats and grooves. [SomaFM]"
+        );
+        assert_eq!(info.uploader, "Ambient Chill");
+        assert_eq!(info.duration, 0);
+        assert!(info.live);
+    }
+
+    #[test]
+    fn invalid_output() {
+        assert!(parse_probe("", "https://example.com/a.mp3", MediaSource::Direct).is_err());
+    }
+
+    #[test]
+    fn internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "198.18.0.1",
+            "198.19.255.254",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:a00:1::",
+            "2002:7f00:1::1",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "1.1.1.1",
+            "100.128.0.1",
+            "198.20.0.1",
+            "2606:4700:4700::1111",
+            "64:ff9b::101:101",
+            "2002:101:101::",
+        ] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_hosts_are_rejected() {
+        assert!(check_host("http://127.0.0.1:8000/stream").await.is_err());
+        assert!(check_host("http://[::1]/song.mp3").await.is_err());
+        assert!(check_host("http://169.254.169.254/latest/").await.is_err());
+        assert!(check_host("file:///etc/passwd").await.is_err());
+        // addresses are connected to as they are, so there is nothing to pin
+        assert_eq!(check_host("https://1.1.1.1/song.mp3").await, Ok(None));
+    }
+
+    #[test]
+    fn audio_responses() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert!(is_audio_response(&headers(&[(
+            "content-type",
+            "audio/mpeg"
+        )])));
+        assert!(is_audio_response(&headers(&[(
+            "content-type",
+            "application/ogg"
+        )])));
+        assert!(is_audio_response(&headers(&[
+            ("content-type", "application/octet-stream"),
+            ("icy-name", "Groove Salad"),
+        ])));
+        assert!(!is_audio_response(&headers(&[(
+            "content-type",
+            "text/html; charset=utf-8"
+        )])));
+        assert!(!is_audio_response(&headers(&[])));
+    }
+}
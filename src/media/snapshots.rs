@@ -0,0 +1,218 @@
+use std::{sync::Arc, time::Duration};
+
+use poise::serenity_prelude::{
+    ButtonStyle, CollectComponentInteraction, Context as SerenityContext, InteractionResponseType,
+    RwLock, TypeMap,
+};
+use songbird::SongbirdKey;
+use tracing::{error, info};
+
+use crate::{
+    config,
+    controls::{connect, permissions::has_dj_access},
+    database::plugin::{get_db_plugin, DatabasePlugin, DatabasePluginKey, QueueSnapshot},
+    message_context::MessageContext,
+    utils::responses,
+};
+
+use super::{
+    global_media_player::GlobalMediaPlayer,
+    plugin::{get_media_player, GlobalMediaPlayerKey},
+};
+
+/// Saves the queue of every guild with a running media player
+pub async fn save_snapshots(data: &RwLock<TypeMap>) {
+    let data = data.read().await;
+
+    let (db, media_player, manager) = match (
+        data.get::<DatabasePluginKey>(),
+        data.get::<GlobalMediaPlayerKey>(),
+        data.get::<SongbirdKey>(),
+    ) {
+        (Some(db), Some(media_player), Some(manager)) => (db, media_player, manager),
+        _ => return,
+    };
+
+    for guild_id in media_player.guild_ids().await {
+        let voice_channel = match manager.get(guild_id) {
+            Some(handler) => handler.lock().await.current_channel(),
+            None => None,
+        };
+
+        let voice_channel = match voice_channel {
+            Some(channel) => channel.0.into(),
+            None => continue,
+        };
+
+        let snapshot = match media_player.snapshot(guild_id, voice_channel).await {
+            Ok(snapshot) => snapshot,
+            Err(_) => continue,
+        };
+
+        if snapshot.now_playing.is_none() && snapshot.queue.is_empty() {
+            continue;
+        }
+
+        match db.save_queue_snapshot(&snapshot) {
+            Ok(_) => info!("Saved queue of {}", guild_id),
+            Err(err) => error!("Unable to save queue of {}: {}", guild_id, err),
+        }
+    }
+}
+
+/// Asks each guild with a saved queue whether it should be restored
+pub async fn offer_restore(ctx: &SerenityContext) {
+    let db = get_db_plugin(ctx).await.unwrap();
+    let media_player = get_media_player(ctx).await.unwrap();
+
+    let snapshots = match db.get_queue_snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(err) => {
+            error!("Unable to read saved queues: {}", err);
+            return;
+        }
+    };
+
+    for snapshot in snapshots {
+        tokio::spawn(offer_snapshot(
+            ctx.clone(),
+            db.clone(),
+            media_player.clone(),
+            snapshot,
+        ));
+    }
+}
+
+/// Offers restoring a saved queue to DJs. The snapshot is kept until it is restored or
+/// dismissed, so an unanswered offer is made again on the next start.
+async fn offer_snapshot(
+    ctx: SerenityContext,
+    db: Arc<dyn DatabasePlugin>,
+    media_player: Arc<GlobalMediaPlayer>,
+    snapshot: QueueSnapshot,
+) {
+    let song_count = snapshot.queue.len() + snapshot.now_playing.iter().count();
+
+    let message = snapshot
+        .text_channel
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Info")
+                    .description(format!(
+                        "The bot restarted with {} song(s) in the queue. Restore it?",
+                        song_count
+                    ))
+                    .color(config::colors::info())
+            })
+            .components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.label("Restore")
+                            .custom_id("restore_queue")
+                            .style(ButtonStyle::Primary)
+                    })
+                    .create_button(|b| {
+                        b.label("Dismiss")
+                            .custom_id("dismiss_queue")
+                            .style(ButtonStyle::Secondary)
+                    })
+                })
+            })
+        })
+        .await;
+
+    let mut message = match message {
+        Ok(message) => message,
+        Err(err) => {
+            error!("Unable to offer restoring queue: {:?}", err);
+            return;
+        }
+    };
+
+    let description = loop {
+        let Some(mci) = CollectComponentInteraction::new(&ctx)
+            .message_id(message.id)
+            .timeout(Duration::from_secs(600))
+            .await
+        else {
+            break "The saved queue expired".to_string();
+        };
+
+        let allowed = match &mci.member {
+            Some(member) => has_dj_access(&ctx, member).await,
+            None => false,
+        };
+
+        if !allowed {
+            responses::interaction_error(&ctx, &mci, "Only DJs can restore the queue").await;
+            continue;
+        }
+
+        if let Err(err) = mci
+            .create_interaction_response(&ctx, |ir| {
+                ir.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            error!("Unable to respond to interaction: {:?}", err);
+        }
+
+        if mci.data.custom_id != "restore_queue" {
+            forget(&db, &snapshot);
+            break "Dismissed the saved queue".to_string();
+        }
+
+        match restore(&ctx, &media_player, &snapshot).await {
+            Ok(_) => {
+                forget(&db, &snapshot);
+                break format!("Restored {} song(s)", song_count);
+            }
+            Err(err) => break format!("Unable to restore queue: {}", err),
+        }
+    };
+
+    if let Err(err) = message
+        .edit(&ctx, |m| {
+            m.embed(|e| {
+                e.title("Info")
+                    .description(description)
+                    .color(config::colors::info())
+            })
+            .components(|c| c)
+        })
+        .await
+    {
+        error!("Unable to edit restore message: {:?}", err);
+    }
+}
+
+fn forget(db: &Arc<dyn DatabasePlugin>, snapshot: &QueueSnapshot) {
+    if let Err(err) = db.delete_queue_snapshot(snapshot.guild_id) {
+        error!(
+            "Unable to delete saved queue of {}: {}",
+            snapshot.guild_id, err
+        );
+    }
+}
+
+async fn restore(
+    ctx: &SerenityContext,
+    media_player: &Arc<GlobalMediaPlayer>,
+    snapshot: &QueueSnapshot,
+) -> Result<(), String> {
+    let message_ctx = MessageContext {
+        channel: snapshot.text_channel,
+        http: ctx.http.clone(),
+    };
+
+    connect(
+        ctx,
+        media_player,
+        snapshot.guild_id,
+        snapshot.voice_channel,
+        message_ctx,
+    )
+    .await?;
+
+    media_player.restore(snapshot).await
+}
@@ -1,4 +1,5 @@
-use serenity::model::prelude::GuildId;
+use serenity::model::prelude::{GuildId, UserId};
+use std::env;
 
 pub mod queue {
     use super::*;
@@ -10,6 +11,16 @@ pub mod queue {
     pub fn page_size(_guild_id: GuildId) -> usize {
         10
     }
+
+    /// How many finished songs are kept for the previous command
+    pub fn played_size(_guild_id: GuildId) -> usize {
+        50
+    }
+
+    /// How many upcoming songs get their full metadata looked up ahead of playback
+    pub fn hydrate_ahead(_guild_id: GuildId) -> usize {
+        5
+    }
 }
 
 pub mod progress_bar {
@@ -28,6 +39,204 @@ pub mod progress_bar {
     }
 }
 
+pub mod timeouts {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// How long the bot may stay paused before leaving.
+    /// Disabled if PAUSE_TIMEOUT is unset or 0.
+    pub fn pause(_guild_id: GuildId) -> Option<Duration> {
+        env::var("PAUSE_TIMEOUT")
+            .ok()
+            .and_then(|minutes| minutes.parse::<u64>().ok())
+            .filter(|minutes| *minutes > 0)
+            .map(|minutes| Duration::from_secs(minutes * 60))
+    }
+
+    /// How long yt-dlp may take to look up a song. Set by METADATA_TIMEOUT in seconds.
+    pub fn metadata() -> Duration {
+        let seconds = env::var("METADATA_TIMEOUT")
+            .ok()
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .unwrap_or(30);
+
+        Duration::from_secs(seconds)
+    }
+
+    /// How long yt-dlp may take to list a playlist, which is slower than a single song
+    pub fn playlist() -> Duration {
+        metadata() * 4
+    }
+
+    /// How long yt-dlp may take to download a song into the audio cache
+    pub fn download() -> Duration {
+        metadata() * 10
+    }
+
+    /// How long a link may go without sending more of its body before it's closed
+    pub fn read() -> Duration {
+        metadata()
+    }
+
+    /// How long the bot may wait with an empty queue before leaving.
+    /// Disabled if IDLE_TIMEOUT is unset or 0.
+    pub fn idle(_guild_id: GuildId) -> Option<Duration> {
+        env::var("IDLE_TIMEOUT")
+            .ok()
+            .and_then(|minutes| minutes.parse::<u64>().ok())
+            .filter(|minutes| *minutes > 0)
+            .map(|minutes| Duration::from_secs(minutes * 60))
+    }
+
+    /// How long the bot may stay alone in a voice channel before leaving.
+    /// Set ALONE_TIMEOUT to 0 to disable.
+    pub fn alone(_guild_id: GuildId) -> Option<Duration> {
+        let seconds = env::var("ALONE_TIMEOUT")
+            .ok()
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .unwrap_or(60);
+
+        (seconds > 0).then(|| Duration::from_secs(seconds))
+    }
+}
+
+pub mod roles {
+    use serenity::model::prelude::RoleId;
+
+    use super::*;
+
+    /// DJ role of servers that didn't pick their own with the dj-role command. Set by DJ_ROLE_ID.
+    pub fn dj(_guild_id: GuildId) -> Option<RoleId> {
+        env::var("DJ_ROLE_ID")
+            .ok()
+            .and_then(|role_id| role_id.parse::<u64>().ok())
+            .map(RoleId)
+    }
+
+    /// Lets whoever queued the current song use DJ commands on it. Set by DJ_ALLOW_REQUESTER.
+    pub fn allow_requester(_guild_id: GuildId) -> bool {
+        env::var("DJ_ALLOW_REQUESTER").map_or(false, |allow| allow == "true" || allow == "1")
+    }
+}
+
+/// Users allowed to run debug commands. Set OWNER_IDS to a comma separated list of user IDs.
+pub fn owners() -> Vec<UserId> {
+    env::var("OWNER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|user_id| user_id.trim().parse::<u64>().ok())
+        .map(UserId)
+        .collect()
+}
+
+pub mod skip {
+    use super::*;
+
+    /// Share of listeners that must vote to skip a song. Skips are immediate if VOTE_SKIP is unset.
+    pub fn vote_ratio(_guild_id: GuildId) -> Option<f32> {
+        env::var("VOTE_SKIP")
+            .ok()
+            .and_then(|ratio| ratio.parse::<f32>().ok())
+            .filter(|ratio| *ratio > 0.0 && *ratio <= 1.0)
+    }
+
+    /// How many votes are needed to skip with the given number of listeners
+    pub fn required_votes(guild_id: GuildId, listeners: usize) -> usize {
+        match vote_ratio(guild_id) {
+            Some(ratio) => ((listeners as f32 * ratio).ceil() as usize).max(1),
+            None => 1,
+        }
+    }
+}
+
+pub mod search {
+    use crate::media::metadata::SearchBackend;
+
+    use super::*;
+
+    /// Default backends to search, in the order they are tried, for guilds that didn't pick
+    /// their own. Set SEARCH_BACKENDS to a comma separated list, e.g. `youtube,soundcloud`.
+    /// Defaults to YouTube.
+    pub fn backends(_guild_id: GuildId) -> Vec<SearchBackend> {
+        let backends: Vec<SearchBackend> = env::var("SEARCH_BACKENDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(SearchBackend::parse)
+            .collect();
+
+        if backends.is_empty() {
+            vec![SearchBackend::YouTube]
+        } else {
+            backends
+        }
+    }
+}
+
+pub mod cache {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+
+    /// How long looked up songs and searches are cached. Set by METADATA_CACHE_TTL in hours,
+    /// 0 disables the cache.
+    pub fn metadata_ttl() -> Option<Duration> {
+        let hours = env::var("METADATA_CACHE_TTL")
+            .ok()
+            .and_then(|hours| hours.parse::<u64>().ok())
+            .unwrap_or(24);
+
+        (hours > 0).then(|| Duration::from_secs(hours * 60 * 60))
+    }
+
+    /// Directory where finished songs are cached, set by AUDIO_CACHE_DIR. Unset disables the
+    /// audio cache.
+    pub fn audio_dir() -> Option<PathBuf> {
+        env::var("AUDIO_CACHE_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// Size cap of the audio cache in bytes. Set by AUDIO_CACHE_SIZE in MB, defaults to 1024.
+    pub fn audio_size() -> u64 {
+        env::var("AUDIO_CACHE_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<u64>().ok())
+            .unwrap_or(1024)
+            * 1024
+            * 1024
+    }
+
+    /// Longest song that gets cached. Set by AUDIO_CACHE_LENGTH in minutes, defaults to 30.
+    pub fn audio_max_length() -> Duration {
+        let minutes = env::var("AUDIO_CACHE_LENGTH")
+            .ok()
+            .and_then(|minutes| minutes.parse::<u64>().ok())
+            .unwrap_or(30);
+
+        Duration::from_secs(minutes * 60)
+    }
+}
+
+pub mod library {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Directory of local music that can be played, set by MUSIC_DIR. Unset disables the library.
+    pub fn dir() -> Option<PathBuf> {
+        env::var("MUSIC_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    }
+
+    pub fn page_size(_guild_id: GuildId) -> usize {
+        15
+    }
+}
+
 pub mod colors {
     use serenity::utils::Colour;
 
@@ -47,6 +256,10 @@ pub mod colors {
         Colour::PURPLE
     }
 
+    pub fn library() -> Colour {
+        Colour::PURPLE
+    }
+
     pub fn playlist() -> Colour {
         Colour::PURPLE
     }
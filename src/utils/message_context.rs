@@ -176,14 +176,10 @@ impl MessageContext {
                 .enumerate()
                 .map(|(i, info)| {
                     format!(
-                        "**{}) [{}]({})** ({})",
+                        "**{}) {}** ({})",
                         i + 1 + page * page_size,
-                        strings::escape_string(&strings::limit_string_length(
-                            &info.title,
-                            text_len,
-                        )),
-                        info.url,
-                        strings::format_timestamp(info.duration)
+                        strings::song_link(info, text_len),
+                        strings::song_duration(info)
                     )
                 })
                 .collect::<Vec<String>>()
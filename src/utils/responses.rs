@@ -3,14 +3,17 @@ use std::{fmt::Display, future::Future};
 use poise::{
     async_trait,
     serenity_prelude::{
-        ButtonStyle, CollectComponentInteraction, CreateComponents, CreateEmbed, GuildId,
-        InteractionResponseType,
+        ButtonStyle, CollectComponentInteraction, CreateComponents, CreateEmbed, GuildId, Http,
+        InteractionResponseType, MessageComponentInteraction,
     },
     CreateReply, ReplyHandle,
 };
 use tracing::error;
 
-use crate::{media::media_info::MediaInfo, CommandResult, Context};
+use crate::{
+    media::media_info::{MediaInfo, Requester},
+    CommandResult, Context,
+};
 
 use super::{config, strings};
 
@@ -42,6 +45,29 @@ fn check_msg(response_result: Result<ReplyHandle<'_>, serenity::Error>) {
     }
 }
 
+/// Answers a button press with an error that only whoever pressed it can see
+pub async fn interaction_error(
+    http: impl AsRef<Http>,
+    mci: &MessageComponentInteraction,
+    message: impl Display,
+) {
+    if let Err(err) = mci
+        .create_interaction_response(http, |ir| {
+            ir.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.ephemeral(true).embed(|e| {
+                        e.title("Error")
+                            .description(&message)
+                            .color(config::colors::error())
+                    })
+                })
+        })
+        .await
+    {
+        error!("Failed to respond to interaction: {}", err);
+    }
+}
+
 pub fn format_info<'a, 'b>(
     m: &'b mut CreateReply<'a>,
     message: impl Display,
@@ -91,14 +117,41 @@ where
     e.description(
         songs
             .enumerate()
-            .map(|(i, info)| {
-                format!(
-                    "**{}) [{}]({})** ({})",
-                    i + 1 + page * page_size,
-                    strings::escape_string(&strings::limit_string_length(&info.title, text_len,)),
-                    info.url,
-                    strings::format_timestamp(info.duration)
-                )
+            .map(|(i, info)| format_song(i + 1 + page * page_size, info, text_len))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    )
+    .footer(|f| f.text(strings::page_display(page + 1, total, page_size, "track")));
+
+    e
+}
+
+/// Like `format_embed_playlist`, but also shows who queued each song
+pub fn format_embed_queue<'a, 'b, I>(
+    e: &'b mut CreateEmbed,
+    songs: I,
+    total: usize,
+    guild_id: GuildId,
+    page: usize,
+) -> &'b mut CreateEmbed
+where
+    I: Iterator<Item = &'a (MediaInfo, Option<Requester>)>,
+{
+    let page_size = config::queue::page_size(guild_id);
+    let text_len = config::queue::text_length(guild_id);
+
+    e.description(
+        songs
+            .enumerate()
+            .map(|(i, (info, requester))| {
+                let song = format_song(i + 1 + page * page_size, info, text_len);
+
+                match requester {
+                    Some(requester) => {
+                        format!("{} - {}", song, strings::escape_string(&requester.name))
+                    }
+                    None => song,
+                }
             })
             .collect::<Vec<String>>()
             .join("\n"),
@@ -108,6 +161,15 @@ where
     e
 }
 
+fn format_song(number: usize, info: &MediaInfo, text_len: usize) -> String {
+    format!(
+        "**{}) {}** ({})",
+        number,
+        strings::song_link(info, text_len),
+        strings::song_duration(info)
+    )
+}
+
 fn create_buttons(
     c: &mut CreateComponents,
     id: u64,
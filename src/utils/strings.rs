@@ -3,14 +3,14 @@ use std::cmp;
 use regex::Regex;
 use serenity::model::prelude::GuildId;
 
-use crate::config;
+use crate::{config, media::media_info::MediaInfo};
 
 static SENSITIVE_CHARACTERS: [&str; 7] = ["\\", "*", "_", "~", "`", "|", ">"];
 
 /// Escapes all sensitize Discord characters
 /* @see: https://github.com/discord-net/Discord.Net/blob/265da99619a775d23b24326648fe4220bc6beeae/src/Discord.Net.Core/Format.cs#L36 */
-pub fn escape_string(text: &String) -> String {
-    let mut sanitized_text = text.clone();
+pub fn escape_string(text: &str) -> String {
+    let mut sanitized_text = text.to_string();
 
     for i in 0..SENSITIVE_CHARACTERS.len() {
         sanitized_text = sanitized_text.replace(
@@ -44,6 +44,25 @@ pub fn limit_string_length(text: &String, width: usize) -> String {
     return text.clone();
 }
 
+/// Escaped and shortened title of the song, linking to it if it has a web page
+pub fn song_link(info: &MediaInfo, width: usize) -> String {
+    let title = escape_string(&limit_string_length(&info.title, width));
+
+    match info.link() {
+        Some(link) => format!("[{}]({})", title, link),
+        None => title,
+    }
+}
+
+/// Length of the song, or a badge if it is live
+pub fn song_duration(info: &MediaInfo) -> String {
+    if info.live {
+        "LIVE".to_string()
+    } else {
+        format_timestamp(info.duration)
+    }
+}
+
 pub fn create_progress_bar(guild_id: GuildId, percent: f32) -> String {
     let length = config::progress_bar::length(guild_id) as usize;
     let marker = config::progress_bar::marker(guild_id);
@@ -84,8 +103,8 @@ pub fn parse_timestamp(timestamp: &String) -> i64 {
     })
 }
 
-pub fn is_url(text: &String) -> bool {
-    text.starts_with("https://")
+pub fn is_url(text: &str) -> bool {
+    text.starts_with("https://") || text.starts_with("http://")
 }
 
 pub fn page_display(page: usize, total: usize, page_size: usize, units: &str) -> String {
@@ -169,6 +188,63 @@ mod tests {
         }
     }
 
+    mod song_link {
+        use super::song_link;
+        use crate::media::media_info::{MediaInfo, MediaSource};
+
+        #[test]
+        fn web_page() {
+            let info = MediaInfo {
+                url: "https://example.com/song.mp3".to_string(),
+                title: "song_1".to_string(),
+                source: MediaSource::Direct,
+                ..MediaInfo::empty()
+            };
+
+            assert_eq!(
+                song_link(&info, 20),
+                "[song\\_1](https://example.com/song.mp3)"
+            );
+        }
+
+        #[test]
+        fn library_file() {
+            let info = MediaInfo {
+                url: "local:album/song.mp3".to_string(),
+                title: "song".to_string(),
+                source: MediaSource::Library,
+                ..MediaInfo::empty()
+            };
+
+            assert_eq!(song_link(&info, 20), "song");
+        }
+    }
+
+    mod song_duration {
+        use super::song_duration;
+        use crate::media::media_info::MediaInfo;
+
+        #[test]
+        fn timestamp() {
+            let info = MediaInfo {
+                duration: 212,
+                ..MediaInfo::empty()
+            };
+
+            assert_eq!(song_duration(&info), "3:32");
+        }
+
+        #[test]
+        fn live() {
+            let info = MediaInfo {
+                live: true,
+                ..MediaInfo::empty()
+            };
+
+            assert_eq!(song_duration(&info), "LIVE");
+        }
+    }
+
     mod escape_string {
         use super::escape_string;
 